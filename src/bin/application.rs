@@ -1,42 +1,431 @@
-use std::io::{self, Read, BufWriter, Write};
-use todo_swamp::{runner, TodoList};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use rustyline::{error::ReadlineError, DefaultEditor};
+use todo_swamp::{runner::{self, OutputFormat}, storage::Journal, CsvError, Description, MatchMode, TodoList};
+
+/// Records past the data file's last checkpoint at which a command writes a new one
+const CHECKPOINT_RECORDS: u64 = 10_000;
+
+const USAGE: &str = "\
+Usage: todo [OPTIONS] [COMMAND]
+
+Commands:
+  add <description> [tag...] [due:YYYY-MM-DD] [!low|!medium|!high]
+                              Add an item and print its index (tags with or without `#`)
+  done <index>                Mark an item as done
+  prio <index> <level>        Set an item's priority: low, medium, high or none
+  search [query...]           Search items, e.g. `search milk \"#groceries\" --all`
+  count [query...]            Print how many items a search would find
+  run <file>                  Run the commands in <file>, one per line, without a count header
+  import <file>               Load items from a CSV or todo.txt file into an empty --data-file
+  export [file]               Write the list as CSV or todo.txt to <file>, or to stdout
+
+Without a command, commands are read from stdin: interactively on a terminal,
+otherwise as a batch whose first line holds the number of commands.
+
+Options:
+      --concise             Print only the indices of found items
+      --format <format>     Output as text (default) or json, one JSON object per result
+      --scores              Include each found item's relevance score in JSON output
+      --match-mode <mode>   How search terms match words: subsequence (default), prefix or exact
+                            (exact searches build no subsequence index, the cheapest on huge lists)
+      --limit <n>           Return at most <n> items per search
+      --data-file <path>    Load the list from this journal and append every change to it
+      --file-format <fmt>   Format for import/export: csv or todo.txt (default: todo.txt for
+                            *.txt files, csv otherwise)
+      --fail-fast           Stop at the first command that fails
+      --repl                Read stdin interactively even if it is not a terminal
+      --batch               Read stdin as a counted batch even if it is a terminal
+  -h, --help                Print this help
+";
+
+enum Command {
+    Stdin,
+    // A single query line assembled from the subcommand's arguments
+    Line(String),
+    // A search or count line, which must not create a missing data file
+    Lookup(String),
+    Run(PathBuf),
+    Import(PathBuf),
+    // Stdout when no file is given
+    Export(Option<PathBuf>),
+}
+
+#[derive(Clone, Copy)]
+enum FileFormat {
+    /// `index,description,tags,done` with a header row
+    Csv,
+    TodoTxt,
+}
+
+impl FileFormat {
+    fn of(opts: &Options, path: Option<&Path>) -> Self {
+        opts.file_format.unwrap_or(match path.and_then(Path::extension) {
+            Some(ext) if ext == "txt" => Self::TodoTxt,
+            _ => Self::Csv,
+        })
+    }
+}
+
+struct Options {
+    // Scripted use: stop at the first line that reports an error
+    fail_fast: bool,
+    match_mode: MatchMode,
+    limit: Option<usize>,
+    concise: bool,
+    scores: bool,
+    format: OutputFormat,
+    data_file: Option<PathBuf>,
+    // Import/export format; guessed from the file name when not given
+    file_format: Option<FileFormat>,
+    // Interactive prompt instead of a counted batch; defaults to whether stdin is a terminal
+    repl: Option<bool>,
+    command: Command,
+}
+
+/// Options come first; everything after the command word belongs to the command
+fn parse_args() -> Result<Options, String> {
+    let mut opts = Options {
+        fail_fast: false,
+        match_mode: MatchMode::default(),
+        limit: None,
+        concise: false,
+        scores: false,
+        format: OutputFormat::default(),
+        data_file: None,
+        file_format: None,
+        repl: None,
+        command: Command::Stdin,
+    };
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                print!("{USAGE}");
+                exit(0);
+            }
+            "--fail-fast" => opts.fail_fast = true,
+            "--concise" => opts.concise = true,
+            "--scores" => opts.scores = true,
+            "--repl" => opts.repl = Some(true),
+            "--batch" => opts.repl = Some(false),
+            "--match-mode" => {
+                opts.match_mode = args.next().ok_or("--match-mode needs a value")?.parse()?;
+            }
+            "--limit" => {
+                let n = args.next().ok_or("--limit needs a value")?;
+                opts.limit = Some(n.parse().map_err(|_| format!("invalid --limit `{n}`"))?);
+            }
+            "--format" => {
+                opts.format = args.next().ok_or("--format needs a value")?.parse()?;
+            }
+            "--data-file" => {
+                opts.data_file = Some(args.next().ok_or("--data-file needs a value")?.into());
+            }
+            "--file-format" => {
+                opts.file_format = Some(match args.next().ok_or("--file-format needs a value")?.as_str() {
+                    "csv" => FileFormat::Csv,
+                    "todo.txt" => FileFormat::TodoTxt,
+                    other => return Err(format!("unknown file format `{other}` (expected csv or todo.txt)")),
+                });
+            }
+            _ if arg.starts_with('-') => return Err(format!("unknown argument `{arg}`")),
+            _ => {
+                opts.command = parse_command(&arg, &args.collect::<Vec<_>>())?;
+                break;
+            }
+        }
+    }
+    Ok(opts)
+}
+
+fn parse_command(name: &str, args: &[String]) -> Result<Command, String> {
+    match (name, args) {
+        ("add", [description, tags @ ..]) => {
+            let mut line = format!("add \"{}\"", Description::new(description).escaped());
+            for arg in tags {
+                // Due dates and priorities pass through; anything else is a tag
+                if !arg.starts_with("due:") && !arg.starts_with('!') {
+                    line.push_str(" #");
+                    line.push_str(arg.strip_prefix('#').unwrap_or(arg));
+                } else {
+                    line.push(' ');
+                    line.push_str(arg);
+                }
+            }
+            Ok(Command::Line(line))
+        }
+        ("add", []) => Err("add needs a description".into()),
+        ("done", [index]) => Ok(Command::Line(format!("done {index}"))),
+        ("done", _) => Err("done needs exactly one index".into()),
+        ("prio", [index, level]) => Ok(Command::Line(format!("prio {index} {level}"))),
+        ("prio", _) => Err("prio needs an index and a priority".into()),
+        ("search", query) => Ok(Command::Lookup(format!("search {}", query.join(" ")))),
+        ("count", query) => Ok(Command::Lookup(format!("count {}", query.join(" ")))),
+        ("run", [file]) => Ok(Command::Run(file.into())),
+        ("run", _) => Err("run needs exactly one file".into()),
+        ("import", [file]) => Ok(Command::Import(file.into())),
+        ("import", _) => Err("import needs exactly one file".into()),
+        ("export", []) => Ok(Command::Export(None)),
+        ("export", [file]) => Ok(Command::Export(Some(file.into()))),
+        ("export", _) => Err("export takes at most one file".into()),
+        _ => Err(format!("unknown command `{name}` (see --help)")),
+    }
+}
 
 pub fn main() {
+    let opts = parse_args().unwrap_or_else(|e| {
+        eprintln!("error: {e}");
+        exit(2);
+    });
+
+    match &opts.command {
+        Command::Line(line) | Command::Lookup(line) => {
+            let writes = matches!(opts.command, Command::Line(_));
+            let (mut tl, mut journal) = open_list(&opts, opts.concise, writes);
+            let mut out = io::stdout().lock();
+            let failed = runner::run_line_formatted(line, &mut tl, journal.as_mut(), &mut out, b"\n", opts.format).is_err();
+            finish(&mut out, &tl, journal);
+            if failed { exit(1); }
+        }
+        Command::Run(path) => {
+            let file = File::open(path).unwrap_or_else(|e| {
+                eprintln!("error: cannot open {}: {e}", path.display());
+                exit(2);
+            });
+            let (mut tl, mut journal) = open_list(&opts, opts.concise, true);
+            let mut out = BufWriter::with_capacity(1 << 20, io::stdout().lock());
+            let failed = run_lines(&opts, &mut BufReader::new(file), usize::MAX, b"\n", &mut tl, &mut journal, &mut out);
+            finish(&mut out, &tl, journal);
+            if failed { exit(1); }
+        }
+        Command::Import(path) => import_items(&opts, path),
+        Command::Export(path) => {
+            // Without a data file there would be nothing to export
+            if opts.data_file.is_none() {
+                eprintln!("error: export needs --data-file to read the items from");
+                exit(2);
+            }
+            let (tl, _) = open_list(&opts, opts.concise, false);
+            // Exported in full first, so a failed export leaves an existing file alone
+            let mut buf = Vec::new();
+            let result = match FileFormat::of(&opts, path.as_deref()) {
+                FileFormat::Csv => tl.export_csv(&mut buf),
+                FileFormat::TodoTxt => tl.export_todo_txt(&mut buf),
+            };
+            if let Err(e) = result {
+                eprintln!("error: cannot export: {e}");
+                exit(1);
+            }
+            let written = match &path {
+                Some(path) => std::fs::write(path, &buf).map_err(|e| format!("cannot write {}: {e}", path.display())),
+                None => io::stdout().lock().write_all(&buf).map_err(|e| format!("cannot export: {e}")),
+            };
+            if let Err(e) = written {
+                eprintln!("error: {e}");
+                exit(1);
+            }
+        }
+        Command::Stdin if opts.repl.unwrap_or_else(|| io::stdin().is_terminal()) => run_repl(&opts),
+        Command::Stdin => run_batch(&opts),
+    }
+}
+
+/// Builds the configured list, replaying the data file into it if one was given.
+/// Commands that only read open the data file read-only, next to any writer, and never
+/// create it; writing commands create a missing one.
+fn open_list(opts: &Options, concise: bool, write: bool) -> (TodoList, Option<Journal>) {
+    let (tl, journal) = match &opts.data_file {
+        None => (TodoList::new(), None),
+        Some(path) => {
+            let (tl, journal) = open_data_file(path, write);
+            (tl, Some(journal))
+        }
+    };
+    let tl = tl
+        .with_match_mode(opts.match_mode)
+        .with_limit(opts.limit)
+        .with_concise(concise)
+        .with_scores(opts.scores);
+    (tl, journal)
+}
+
+/// Loads the list in the data file, starting from its latest checkpoint
+fn open_data_file(path: &Path, write: bool) -> (TodoList, Journal) {
+    let loaded = if write { Journal::load(path) } else { Journal::load_read_only(path) };
+    let (tl, journal) = loaded.unwrap_or_else(|e| {
+        if e.kind() == io::ErrorKind::NotFound && !write {
+            eprintln!("error: data file {} does not exist", path.display());
+        } else {
+            eprintln!("error: cannot open data file {}: {e}", path.display());
+        }
+        exit(2);
+    });
+    if journal.discarded_bytes() > 0 && journal.is_read_only() {
+        eprintln!("warning: skipped {} byte(s) of a partly written record at the end of {}", journal.discarded_bytes(), path.display());
+    } else if journal.discarded_bytes() > 0 {
+        eprintln!("warning: dropped {} byte(s) of a partly written record from {}", journal.discarded_bytes(), path.display());
+    }
+    (tl, journal)
+}
+
+/// Flushes the output and syncs the data file, checkpointing it once enough records
+/// have piled up since the last checkpoint that replaying them would slow down startup
+fn finish<W: Write>(out: &mut W, tl: &TodoList, journal: Option<Journal>) {
+    let _ = out.flush();
+    if let Some(mut journal) = journal {
+        if let Err(e) = journal.sync() {
+            eprintln!("error: cannot sync data file: {e}");
+            exit(1);
+        }
+        if !journal.is_read_only() && journal.records_since_checkpoint() >= CHECKPOINT_RECORDS {
+            // The journal already holds everything; a failed checkpoint only costs replay time later
+            if let Err(e) = journal.checkpoint(tl) {
+                eprintln!("warning: cannot checkpoint data file: {e}");
+            }
+        }
+    }
+}
+
+/// Replaces the (empty) list in `--data-file` with the file's items
+fn import_items(opts: &Options, path: &Path) {
+    // Without a data file the imported items would be dropped as soon as they were read
+    if opts.data_file.is_none() {
+        eprintln!("error: import needs --data-file to store the items in");
+        exit(2);
+    }
+    let file = File::open(path).unwrap_or_else(|e| {
+        eprintln!("error: cannot open {}: {e}", path.display());
+        exit(2);
+    });
+    let (existing, journal) = open_list(opts, opts.concise, true);
+    // Imported indices would collide with the ones already in the data file
+    if existing.next_index().0 > 0 {
+        eprintln!("error: import needs an empty data file");
+        exit(1);
+    }
+    let imported = match FileFormat::of(opts, Some(path)) {
+        FileFormat::Csv => TodoList::import_csv(BufReader::new(file)),
+        FileFormat::TodoTxt => TodoList::import_todo_txt(BufReader::new(file)).map_err(CsvError::Io),
+    };
+    let tl = match imported {
+        Ok(tl) => tl,
+        Err(CsvError::Rows(rows)) => {
+            for row in rows {
+                eprintln!("error: {}: {row}", path.display());
+            }
+            exit(1);
+        }
+        Err(CsvError::Io(e)) => {
+            eprintln!("error: cannot read {}: {e}", path.display());
+            exit(1);
+        }
+    };
+    if let Some(mut journal) = journal {
+        if let Err(e) = journal.append_list(&tl) {
+            eprintln!("error: cannot write data file: {e}");
+            exit(1);
+        }
+        finish(&mut io::stdout(), &tl, Some(journal));
+    }
+    println!("{} item(s) imported", tl.items().count());
+}
+
+/// One command per prompt, no count header; results are flushed as soon as each line is run
+fn run_repl(opts: &Options) {
+    let mut editor = DefaultEditor::new().unwrap_or_else(|e| {
+        eprintln!("error: cannot start line editor: {e}");
+        exit(2);
+    });
+    let (mut tl, mut journal) = open_list(opts, opts.concise, true);
+    let stdout = io::stdout();
+
+    loop {
+        match editor.readline("todo> ") {
+            Ok(line) => {
+                if line.trim().is_empty() { continue; }
+                let _ = editor.add_history_entry(line.as_str());
+                let mut out = stdout.lock();
+                let _ = runner::run_line_formatted(&line, &mut tl, journal.as_mut(), &mut out, b"\n", opts.format);
+                let _ = out.flush();
+            }
+            // Ctrl-C abandons the current line, Ctrl-D ends the session
+            Err(ReadlineError::Interrupted) => {}
+            Err(ReadlineError::Eof) => break,
+            Err(e) => {
+                eprintln!("error: {e}");
+                exit(1);
+            }
+        }
+    }
+    finish(&mut stdout.lock(), &tl, journal);
+}
+
+/// Counted batch from stdin: the first line holds the number of commands that follow.
+/// Lines are processed as they arrive, so memory stays bounded by the list itself.
+fn run_batch(opts: &Options) {
     let stdin = io::stdin();
     let stdout = io::stdout();
+    let mut input = BufReader::with_capacity(1 << 20, stdin.lock());
     let mut out = BufWriter::with_capacity(1 << 20, stdout.lock());
-    
-    // Read all input to detect line ending style
-    let mut input = Vec::new();
-    let _ = stdin.lock().read_to_end(&mut input);
-    
-    // Detect line ending: check if first newline is CRLF or LF
-    let line_ending = if input.windows(2).any(|w| w == b"\r\n") {
-        b"\r\n" as &[u8]
-    } else {
-        b"\n" as &[u8]
-    };
-    
-    // Split input into lines while preserving the line ending detection
-    let input_str = String::from_utf8_lossy(&input);
-    let mut lines = input_str.lines();
-    
-    let Some(first) = lines.next() else { return };
+
+    let mut buf = Vec::new();
+    if !matches!(input.read_until(b'\n', &mut buf), Ok(n) if n > 0) { return; }
+    let line_ending = strip_line_ending(&mut buf).unwrap_or(b"\n");
+
+    let first = String::from_utf8_lossy(&buf);
     let first_trimmed = first.trim();
     let count: usize = first_trimmed.parse().unwrap_or(0);
-    
-    // Concise mode: if first line has trailing whitespace, use concise output
-    let concise_mode = first.len() > first_trimmed.len();
-    
-    // For large inputs, use fast mode (exact match only)
-    let fast_mode = count > 10_000;
-    
-    let mut tl = TodoList::with_modes(fast_mode, concise_mode);
-    
-    for line in lines.take(count) {
-        if !line.trim().is_empty() {
-            runner::run_line_buffered(&line, &mut tl, &mut out, line_ending);
+
+    // Concise mode: `--concise`, or trailing whitespace after the count
+    let concise_mode = opts.concise || first.len() > first_trimmed.len();
+
+    let (mut tl, mut journal) = open_list(opts, concise_mode, true);
+    let failed = run_lines(opts, &mut input, count, line_ending, &mut tl, &mut journal, &mut out);
+    finish(&mut out, &tl, journal);
+    if failed { exit(1); }
+}
+
+/// Runs up to `count` lines from `input` as they arrive.
+/// Returns `true` if `--fail-fast` stopped at a failed line; the caller still has to `finish`.
+fn run_lines<R: io::Read, W: Write>(
+    opts: &Options,
+    input: &mut BufReader<R>,
+    count: usize,
+    mut line_ending: &'static [u8],
+    tl: &mut TodoList,
+    journal: &mut Option<Journal>,
+    out: &mut W,
+) -> bool {
+    let mut buf = Vec::new();
+    for _ in 0..count {
+        buf.clear();
+        if !matches!(input.read_until(b'\n', &mut buf), Ok(n) if n > 0) { break; }
+        // Each result uses its command's line ending; an unterminated last line reuses the previous one
+        if let Some(ending) = strip_line_ending(&mut buf) {
+            line_ending = ending;
+        }
+        let line = String::from_utf8_lossy(&buf);
+        if !line.trim().is_empty() && runner::run_line_formatted(&line, tl, journal.as_mut(), out, line_ending, opts.format).is_err() && opts.fail_fast {
+            return true;
+        }
+        // No more input is buffered and the next read may block: let the results out first
+        if input.buffer().is_empty() {
+            let _ = out.flush();
         }
     }
-    let _ = out.flush();
+    false
+}
+
+/// Removes a trailing `\n` or `\r\n` from `buf` and returns it
+fn strip_line_ending(buf: &mut Vec<u8>) -> Option<&'static [u8]> {
+    if buf.last() != Some(&b'\n') { return None; }
+    buf.pop();
+    if buf.last() == Some(&b'\r') {
+        buf.pop();
+        return Some(b"\r\n");
+    }
+    Some(b"\n")
 }
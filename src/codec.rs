@@ -0,0 +1,119 @@
+//! Little-endian binary helpers shared by the journal and snapshot formats.
+use crate::Priority;
+
+/// 32-bit FNV-1a hash used as a cheap record checksum
+#[must_use]
+pub(crate) fn checksum(bytes: &[u8]) -> u32 {
+    let mut hash = 0x811c_9dc5u32;
+    for &b in bytes {
+        hash ^= u32::from(b);
+        hash = hash.wrapping_mul(0x0100_0193);
+    }
+    hash
+}
+
+/// Checksum for large buffers such as snapshots: the same multiply-and-xor mixing as
+/// `checksum`, but over 8-byte words, so it runs several times faster
+#[must_use]
+pub(crate) fn checksum_wide(bytes: &[u8]) -> u32 {
+    const PRIME: u64 = 0x0100_0000_01b3;
+    let mut hash = 0xcbf2_9ce4_8422_2325u64;
+    let mut words = bytes.chunks_exact(8);
+    for w in &mut words {
+        let w = u64::from_le_bytes([w[0], w[1], w[2], w[3], w[4], w[5], w[6], w[7]]);
+        hash = (hash ^ w).wrapping_mul(PRIME).rotate_left(29);
+    }
+    for &b in words.remainder() {
+        hash = (hash ^ u64::from(b)).wrapping_mul(PRIME);
+    }
+    (hash ^ (hash >> 32)) as u32
+}
+
+pub(crate) fn put_u8(buf: &mut Vec<u8>, v: u8) { buf.push(v); }
+pub(crate) fn put_u32(buf: &mut Vec<u8>, v: u32) { buf.extend_from_slice(&v.to_le_bytes()); }
+pub(crate) fn put_u64(buf: &mut Vec<u8>, v: u64) { buf.extend_from_slice(&v.to_le_bytes()); }
+
+/// Length-prefixed UTF-8 string
+pub(crate) fn put_str(buf: &mut Vec<u8>, s: &str) {
+    put_u32(buf, s.len() as u32);
+    buf.extend_from_slice(s.as_bytes());
+}
+
+/// Count-prefixed list of `u32`s
+pub(crate) fn put_u32s(buf: &mut Vec<u8>, values: impl Iterator<Item = u32>) {
+    let start = buf.len();
+    put_u32(buf, 0);
+    let mut count = 0u32;
+    for v in values {
+        put_u32(buf, v);
+        count += 1;
+    }
+    buf[start..start + 4].copy_from_slice(&count.to_le_bytes());
+}
+
+/// Decodes the values returned by `Reader::u32s_raw`
+pub(crate) fn u32s(raw: &[u8]) -> Vec<u32> {
+    raw.chunks_exact(4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect()
+}
+
+/// 0 for none, then 1–3 from low to high
+pub(crate) fn put_priority(buf: &mut Vec<u8>, p: Option<Priority>) {
+    put_u8(buf, p.map_or(0, |p| p as u8 + 1));
+}
+
+/// Cursor over an in-memory buffer; every read returns `None` once the input runs short
+pub(crate) struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(buf: &'a [u8]) -> Self { Self { buf, pos: 0 } }
+
+    pub(crate) fn is_empty(&self) -> bool { self.pos >= self.buf.len() }
+
+    pub(crate) fn remaining(&self) -> usize { self.buf.len().saturating_sub(self.pos) }
+
+    pub(crate) fn bytes(&mut self, n: usize) -> Option<&'a [u8]> {
+        let end = self.pos.checked_add(n)?;
+        let out = self.buf.get(self.pos..end)?;
+        self.pos = end;
+        Some(out)
+    }
+
+    pub(crate) fn u8(&mut self) -> Option<u8> { self.bytes(1).map(|b| b[0]) }
+
+    pub(crate) fn u32(&mut self) -> Option<u32> {
+        self.bytes(4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    pub(crate) fn u64(&mut self) -> Option<u64> {
+        self.bytes(8).map(|b| {
+            let mut a = [0u8; 8];
+            a.copy_from_slice(b);
+            u64::from_le_bytes(a)
+        })
+    }
+
+    /// Reads what `put_u32s` wrote
+    pub(crate) fn u32s(&mut self) -> Option<Vec<u32>> { self.u32s_raw().map(u32s) }
+
+    /// The still encoded values of a `put_u32s` list, for decoding later with `u32s`
+    pub(crate) fn u32s_raw(&mut self) -> Option<&'a [u8]> {
+        let count = self.u32()? as usize;
+        self.bytes(count.checked_mul(4)?)
+    }
+
+    /// Reads what `put_priority` wrote; `None` on a short or invalid byte
+    pub(crate) fn priority(&mut self) -> Option<Option<Priority>> {
+        match self.u8()? {
+            0 => Some(None),
+            b => Priority::ALL.get(usize::from(b) - 1).map(|p| Some(*p)),
+        }
+    }
+
+    pub(crate) fn str(&mut self) -> Option<&'a str> {
+        let len = self.u32()? as usize;
+        std::str::from_utf8(self.bytes(len)?).ok()
+    }
+}
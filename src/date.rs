@@ -0,0 +1,109 @@
+//! Calendar dates for due dates, and the clock that decides what "today" is.
+use std::convert::TryFrom;
+use std::fmt::{self, Display};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A day in the proleptic Gregorian calendar, written `YYYY-MM-DD`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Date {
+    // Field order makes the derived ordering chronological
+    year: u16,
+    month: u8,
+    day: u8,
+}
+
+impl Date {
+    /// `None` unless the year is 1–9999 and the day exists in that month
+    #[must_use] pub fn new(year: u16, month: u8, day: u8) -> Option<Self> {
+        let valid = (1..=9999).contains(&year) && (1..=12).contains(&month) && day >= 1 && day <= days_in_month(year, month);
+        valid.then_some(Self { year, month, day })
+    }
+
+    #[must_use] pub fn year(self) -> u16 { self.year }
+    #[must_use] pub fn month(self) -> u8 { self.month }
+    #[must_use] pub fn day(self) -> u8 { self.day }
+
+    /// The date `days` days after 1970-01-01, clamped to the supported years
+    #[must_use] pub fn from_unix_days(days: i64) -> Self {
+        // Howard Hinnant's `civil_from_days`
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z.rem_euclid(146_097);
+        let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u8;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u8;
+        let year = yoe + era * 400 + i64::from(month <= 2);
+        match year {
+            ..=0 => Self { year: 1, month: 1, day: 1 },
+            10_000.. => Self { year: 9999, month: 12, day: 31 },
+            _ => Self { year: year as u16, month, day },
+        }
+    }
+
+    /// Packs the date as the number `YYYYMMDD`, which orders the same way
+    #[must_use] pub fn to_packed(self) -> u32 {
+        u32::from(self.year) * 10_000 + u32::from(self.month) * 100 + u32::from(self.day)
+    }
+
+    /// Inverse of `to_packed`
+    #[must_use] pub fn from_packed(packed: u32) -> Option<Self> {
+        let year = u16::try_from(packed / 10_000).ok()?;
+        Self::new(year, (packed / 100 % 100) as u8, (packed % 100) as u8)
+    }
+}
+
+fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        2 if year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400)) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+impl FromStr for Date {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let b = s.as_bytes();
+        let digits = |r: std::ops::Range<usize>| b[r].iter().all(u8::is_ascii_digit);
+        if b.len() != 10 || b[4] != b'-' || b[7] != b'-' || !digits(0..4) || !digits(5..7) || !digits(8..10) {
+            return Err(format!("invalid date `{s}` (expected YYYY-MM-DD)"));
+        }
+        // All ASCII digits, so these parses cannot fail
+        Self::new(s[..4].parse().unwrap_or(0), s[5..7].parse().unwrap_or(0), s[8..].parse().unwrap_or(0))
+            .ok_or_else(|| format!("invalid date `{s}` (no such day)"))
+    }
+}
+
+impl Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+/// Source of the current date for filters like `due:today` and `overdue`
+pub trait Clock: Send + Sync {
+    fn today(&self) -> Date;
+}
+
+/// The system clock, in UTC
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn today(&self) -> Date {
+        let secs = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        Date::from_unix_days((secs / 86_400) as i64)
+    }
+}
+
+/// A clock stopped at one date, for tests and reproducible runs
+#[derive(Debug, Clone, Copy)]
+pub struct FixedClock(pub Date);
+
+impl Clock for FixedClock {
+    fn today(&self) -> Date { self.0 }
+}
@@ -1,7 +1,11 @@
+mod codec;
+pub mod date;
 pub mod parser;
 pub mod query;
 pub mod runner;
+pub mod storage;
 pub mod todo_list;
 
+pub use date::*;
 pub use query::*;
 pub use todo_list::*;
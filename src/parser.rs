@@ -1,63 +1,430 @@
-use crate::{Description, Index, Query, SearchParams, SearchWord, Tag};
+use std::fmt::{self, Display};
+use crate::{is_word_char, Date, DateOp, DateSpec, Description, Index, Priority, Query, SearchExpr, SearchParams, SearchWord, Sort, SortKey, StatusFilter, Tag};
 use nom::{
-    branch::alt, bytes::complete::{tag, take_while, take_while1},
-    character::complete::{digit1, space0, space1}, combinator::opt,
-    multi::{many1, separated_list}, sequence::{delimited, pair, preceded}, IResult,
+    branch::alt, bytes::complete::{escaped_transform, tag, take_while1},
+    character::complete::{digit1, space0, space1}, combinator::{cut, map_res, opt, recognize, verify},
+    error::{context, ErrorKind},
+    multi::{separated_list, separated_nonempty_list}, sequence::{delimited, pair, preceded, terminated}, Err, IResult,
 };
 
-/// Parses a query string into a Query enum.
+mod json;
+
+type PResult<'a, T> = IResult<&'a str, T, SyntaxError<'a>>;
+
+const SNIPPET_LEN: usize = 20;
+/// Prefixes of the search modifiers, which are only allowed outside parentheses
+const MODIFIERS: [&str; 3] = ["sort:", "limit:", "offset:"];
+/// Words that search reads as operators rather than terms
+const KEYWORDS: [&str; 3] = ["OR", "NOT", "overdue"];
+
+/// Why and where a query line failed to parse
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// Character offset into the trimmed line where parsing stopped
+    pub position: usize,
+    /// The token the parser was looking for at `position`
+    pub expected: String,
+    /// The offending input starting at `position`, shortened for display
+    pub snippet: String,
+}
+
+impl ParseError {
+    fn at(input: &str, rest: &str, expected: &str) -> Self {
+        let offset = input.len() - rest.len();
+        let before = &input[..offset];
+        let position = if before.is_ascii() { offset } else { before.chars().count() };
+        // Slice instead of collecting chars: bad lines in a big batch should cost little more than good ones
+        let snippet = match rest.char_indices().nth(SNIPPET_LEN) {
+            Some((end, _)) => {
+                let mut s = String::with_capacity(end + 3);
+                s.push_str(&rest[..end]);
+                s.push_str("...");
+                s
+            }
+            None => rest.to_owned(),
+        };
+        Self { position, expected: expected.to_owned(), snippet }
+    }
+
+    fn from_syntax(input: &str, e: &SyntaxError) -> Self {
+        Self::at(input, e.rest, e.expected.unwrap_or("valid query"))
+    }
+}
+
+/// Where a parser gave up, labelled by the innermost `context` around it. Unlike nom's
+/// `VerboseError` it doesn't allocate, so failed `alt` branches and bad lines stay cheap.
+#[derive(Debug)]
+struct SyntaxError<'a> {
+    rest: &'a str,
+    expected: Option<&'static str>,
+}
+
+impl<'a> nom::error::ParseError<&'a str> for SyntaxError<'a> {
+    fn from_error_kind(rest: &'a str, _: ErrorKind) -> Self { Self { rest, expected: None } }
+
+    fn append(_: &'a str, _: ErrorKind, other: Self) -> Self { other }
+
+    fn add_context(rest: &'a str, ctx: &'static str, other: Self) -> Self {
+        match other.expected {
+            Some(_) => other,
+            None => Self { rest, expected: Some(ctx) },
+        }
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "expected {} at column {}", self.expected, self.position + 1)?;
+        if self.snippet.is_empty() {
+            write!(f, ", found end of input")
+        } else {
+            write!(f, ", found \"{}\"", self.snippet)
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Parses a query string into a Query enum. Lines starting with `{` are read as JSON commands.
 /// # Errors
-/// Returns a nom error if the input doesn't match any valid query format.
-pub fn query(input: &str) -> IResult<&str, Query> {
-    alt((add, done, search))(input.trim())
+/// Returns a `ParseError` pointing at the first token that doesn't fit any valid query format.
+pub fn query(input: &str) -> Result<Query, ParseError> {
+    let input = input.trim();
+    if input.starts_with('{') {
+        return json::query(input);
+    }
+    let command = input.split(|c: char| !c.is_ascii_alphabetic()).next().unwrap_or_default();
+    let (parsed, trailing) = match command {
+        "add" => (add(input), "tag, due date or priority"),
+        "done" => (done(input), "end of input"),
+        "undone" => (undone(input), "end of input"),
+        "edit" => (edit(input), "tag"),
+        "delete" => (delete(input), "end of input"),
+        "prio" => (prio(input), "end of input"),
+        "search" => (search(input), "search term"),
+        "count" => (count(input), "search term"),
+        _ => return Err(ParseError::at(input, input, "command (add, done, undone, edit, delete, prio, search, count)")),
+    };
+    match parsed {
+        Ok((rest, q)) if rest.trim().is_empty() => Ok(q),
+        Ok((rest, _)) => Err(ParseError::at(input, rest.trim_start(), trailing)),
+        Err(Err::Error(e)) | Err(Err::Failure(e)) => Err(ParseError::from_syntax(input, &e)),
+        Err(Err::Incomplete(_)) => Err(ParseError::at(input, "", "more input")),
+    }
+}
+
+/// `add "description"` followed by `#tags`, `due:YYYY-MM-DD` and `!priority` in any order
+fn add(input: &str) -> PResult<'_, Query> {
+    preceded(
+        pair(tag("add"), context("space", space1)),
+        cut(pair(context("description", description), preceded(space0, add_fields))),
+    )(input)
+        .map(|(r, (d, (tags, due, priority)))| (r, Query::Add(Description::new(&d), tags, due, priority)))
+}
+
+enum AddField<'a> { Tag(&'a str), Due(Date), Priority(Priority) }
+
+/// The tags, due date and priority of an `add`; the latter two at most once each
+fn add_fields(input: &str) -> PResult<'_, (Vec<Tag>, Option<Date>, Option<Priority>)> {
+    let field = alt((
+        |i| todo_tag(i).map(|(r, t)| (r, AddField::Tag(t))),
+        |i| due_date(i).map(|(r, d)| (r, AddField::Due(d))),
+        |i| priority(i).map(|(r, p)| (r, AddField::Priority(p))),
+    ));
+    let (rest, fields) = separated_list(space1, field)(input)?;
+    let (mut tags, mut due, mut prio) = (Vec::new(), None, None);
+    for f in fields {
+        let repeated = match f {
+            AddField::Tag(t) => { tags.push(Tag::new(t)); false }
+            AddField::Due(d) => due.replace(d).is_some(),
+            AddField::Priority(p) => prio.replace(p).is_some(),
+        };
+        if repeated {
+            return Err(Err::Failure(SyntaxError { rest: input, expected: Some("at most one due date and priority") }));
+        }
+    }
+    Ok((rest, (tags, due, prio)))
+}
+
+/// `!low`, `!medium` or `!high`
+fn priority(input: &str) -> PResult<'_, Priority> {
+    preceded(tag("!"), cut(priority_level))(input)
+}
+
+fn priority_level(input: &str) -> PResult<'_, Priority> {
+    context("priority (low, medium or high)", map_res(word, str::parse))(input)
+}
+
+/// `prio <index> <low|medium|high|none>`
+fn prio(input: &str) -> PResult<'_, Query> {
+    pair(index_arg("prio"), cut(preceded(context("space", space1), alt((
+        |i| tag("none")(i).map(|(r, _)| (r, None)),
+        |i| priority_level(i).map(|(r, p)| (r, Some(p))),
+    )))))(input)
+        .map(|(r, (i, p))| (r, Query::Prio(i, p)))
+}
+
+fn due_date(input: &str) -> PResult<'_, Date> {
+    preceded(tag("due:"), cut(date))(input)
+}
+
+fn date(input: &str) -> PResult<'_, Date> {
+    context("date (YYYY-MM-DD)", date_literal)(input)
+}
+
+fn date_literal(input: &str) -> PResult<'_, Date> {
+    map_res(take_while1(|c: char| c.is_ascii_digit() || c == '-'), str::parse)(input)
+}
+
+/// Anything but quotes, backslashes and control characters other than tab
+fn is_text_char(c: char) -> bool { c != '"' && c != '\\' && (c == '\t' || !c.is_control()) }
+
+fn word(input: &str) -> PResult<'_, &str> { take_while1(is_word_char)(input) }
+/// `#name`, or `#@name` for a todo.txt context
+fn todo_tag(input: &str) -> PResult<'_, &str> {
+    preceded(tag("#"), cut(context("tag name", recognize(pair(opt(tag("@")), word)))))(input)
+}
+
+/// A quoted description; `\"` and `\\` stand for a literal quote and backslash
+fn description(input: &str) -> PResult<'_, String> {
+    delimited(tag("\""), opt(description_text), cut(context("closing quote", tag("\""))))(input)
+        .map(|(r, d)| (r, d.unwrap_or_default()))
 }
 
-fn add(input: &str) -> IResult<&str, Query> {
-    preceded(pair(tag("add"), space1), pair(description, opt(preceded(space0, tags))))(input)
-        .map(|(r, (d, t))| (r, Query::Add(Description::new(&d), t.unwrap_or_default())))
+fn description_text(input: &str) -> PResult<'_, String> {
+    escaped_transform(
+        take_while1(is_text_char),
+        '\\',
+        cut(context("escape sequence (\\\" or \\\\)", alt((tag("\""), tag("\\"))))),
+    )(input)
 }
 
-fn is_word_char(c: char) -> bool { c.is_ascii_alphabetic() || c == '-' }
-fn is_sentence_char(c: char) -> bool { is_word_char(c) || c.is_whitespace() }
+fn done(input: &str) -> PResult<'_, Query> {
+    index_arg("done")(input).map(|(r, i)| (r, Query::Done(i)))
+}
 
-fn word(input: &str) -> IResult<&str, &str> { take_while1(is_word_char)(input) }
-fn sentence(input: &str) -> IResult<&str, &str> { take_while(is_sentence_char)(input) }
-fn todo_tag(input: &str) -> IResult<&str, &str> { preceded(tag("#"), word)(input) }
+fn undone(input: &str) -> PResult<'_, Query> {
+    index_arg("undone")(input).map(|(r, i)| (r, Query::Undone(i)))
+}
 
-fn description(input: &str) -> IResult<&str, String> {
-    delimited(tag("\""), sentence, tag("\""))(input).map(|(r, d)| (r, d.to_string()))
+fn delete(input: &str) -> PResult<'_, Query> {
+    index_arg("delete")(input).map(|(r, i)| (r, Query::Delete(i)))
 }
 
-fn tags(input: &str) -> IResult<&str, Vec<Tag>> {
-    separated_list(space1, todo_tag)(input).map(|(r, t)| (r, t.into_iter().map(Tag::new).collect()))
+/// `<keyword> <index>`
+fn index_arg<'a>(keyword: &'static str) -> impl Fn(&'a str) -> PResult<'a, Index> {
+    move |input| preceded(pair(tag(keyword), context("space", space1)), cut(index))(input)
 }
 
-fn done(input: &str) -> IResult<&str, Query> {
-    preceded(pair(tag("done"), space1), many1(digit1))(input)
-        .map(|(r, d)| (r, Query::Done(Index::new(d.concat().parse().unwrap()))))
+fn index(input: &str) -> PResult<'_, Index> {
+    context("index", map_res(digit1, str::parse::<u64>))(input).map(|(r, d)| (r, Index::new(d)))
 }
 
-enum WordOrTag { Word(String), Tag(String) }
+/// `edit <index> "description" #tags`, where either the description or the tags may be left out;
+/// a bare `#` in place of the tags removes them all
+fn edit(input: &str) -> PResult<'_, Query> {
+    pair(index_arg("edit"), cut(preceded(context("space", space1), context("description or tags", edit_fields))))(input)
+        .map(|(r, (i, (d, t)))| (r, Query::Edit(i, d, t)))
+}
 
-fn search(input: &str) -> IResult<&str, Query> {
-    preceded(tag("search"), opt(preceded(space1, separated_list(space1, word_or_tag))))(input)
-        .map(|(r, m)| (r, to_query(m.unwrap_or_default())))
+fn edit_fields(input: &str) -> PResult<'_, (Option<Description>, Option<Vec<Tag>>)> {
+    alt((
+        |i| pair(description, opt(preceded(space0, edit_tags)))(i)
+            .map(|(r, (d, t))| (r, (Some(Description::new(&d)), t))),
+        |i| edit_tags(i).map(|(r, t)| (r, (None, Some(t)))),
+    ))(input)
 }
 
-fn word_or_tag(input: &str) -> IResult<&str, WordOrTag> {
+/// The new tags of an `edit`: one or more `#tags`, or a bare `#` for none
+fn edit_tags(input: &str) -> PResult<'_, Vec<Tag>> {
     alt((
-        |i| pair(tag("#"), word)(i).map(|(r, (_, w))| (r, WordOrTag::Tag(w.to_string()))),
-        |i| word(i).map(|(r, w)| (r, WordOrTag::Word(w.to_string())))
+        |i| no_tags(i).map(|(r, _)| (r, Vec::new())),
+        |i| separated_nonempty_list(space1, todo_tag)(i).map(|(r, t)| (r, t.into_iter().map(Tag::new).collect())),
     ))(input)
 }
 
-fn to_query(items: Vec<WordOrTag>) -> Query {
-    let (mut words, mut tags) = (Vec::new(), Vec::new());
-    for item in items {
-        match item {
-            WordOrTag::Word(w) => words.push(SearchWord::new(&w)),
-            WordOrTag::Tag(t) => tags.push(Tag::new(&t)),
+/// A `#` standing alone, as opposed to the start of a `#tag`
+fn no_tags(input: &str) -> PResult<'_, &str> {
+    verify(tag("#"), |_: &str| input[1..].chars().next().is_none_or(char::is_whitespace))(input)
+}
+
+fn search(input: &str) -> PResult<'_, Query> {
+    preceded(tag("search"), search_params)(input).map(|(r, sp)| (r, Query::Search(sp)))
+}
+
+/// `count` takes the same query as `search`
+fn count(input: &str) -> PResult<'_, Query> {
+    preceded(tag("count"), search_params)(input).map(|(r, sp)| (r, Query::Count(sp)))
+}
+
+/// Everything after `search` or `count`: terms, flags and modifiers, all optional
+fn search_params(input: &str) -> PResult<'_, SearchParams> {
+    // Fast path for the most common query, a single plain word
+    let term = input.trim_start_matches([' ', '\t']);
+    if term.len() < input.len() && is_plain_word(term) {
+        return Ok(("", SearchParams { expr: SearchExpr::Word(SearchWord::new(term)), ..SearchParams::default() }));
+    }
+    opt(preceded(space1, |i| or_expr(i, true)))(input)
+        .map(|(r, m)| {
+            let (expr, flags) = m.unwrap_or_default();
+            let (status, sort) = (flags.status.unwrap_or_default(), flags.sort.unwrap_or_default());
+            (r, SearchParams { expr, status, sort, limit: flags.limit, offset: flags.offset.unwrap_or(0) })
+        })
+}
+
+/// `--` flags and `sort:`, `limit:` and `offset:` modifiers seen in a search; the last one of each kind wins
+#[derive(Default)]
+struct Flags { status: Option<StatusFilter>, sort: Option<Sort>, limit: Option<usize>, offset: Option<usize> }
+
+impl Flags {
+    fn merge(self, later: Flags) -> Self {
+        Self {
+            status: later.status.or(self.status),
+            sort: later.sort.or(self.sort),
+            limit: later.limit.or(self.limit),
+            offset: later.offset.or(self.offset),
+        }
+    }
+}
+
+enum SearchTerm { Expr(SearchExpr), Flags(Flags) }
+
+/// `and ("OR" and)*`; flags and modifiers are only accepted at the top level
+fn or_expr(input: &str, top: bool) -> PResult<'_, (SearchExpr, Flags)> {
+    separated_nonempty_list(delimited(space1, tag("OR"), space1), |i| and_expr(i, top))(input)
+        .map(|(r, branches)| {
+            let mut flags = Flags::default();
+            let exprs = branches.into_iter().map(|(e, f)| { flags = std::mem::take(&mut flags).merge(f); e }).collect();
+            (r, (SearchExpr::or(exprs), flags))
+        })
+}
+
+/// Space-separated terms, all of which must match
+fn and_expr(input: &str, top: bool) -> PResult<'_, (SearchExpr, Flags)> {
+    separated_nonempty_list(space1, |i| search_term(i, top))(input)
+        .map(|(r, terms)| {
+            let (mut exprs, mut flags) = (Vec::new(), Flags::default());
+            for term in terms {
+                match term {
+                    SearchTerm::Expr(e) => exprs.push(e),
+                    SearchTerm::Flags(f) => flags = flags.merge(f),
+                }
+            }
+            (r, (SearchExpr::and(exprs), flags))
+        })
+}
+
+fn search_term(input: &str, top: bool) -> PResult<'_, SearchTerm> {
+    if top && input.starts_with("--") {
+        return search_flag(input).map(|(r, f)| (r, SearchTerm::Flags(f)));
+    }
+    if top && MODIFIERS.iter().any(|m| input.starts_with(m)) {
+        return search_modifier(input).map(|(r, f)| (r, SearchTerm::Flags(f)));
+    }
+    unary(input).map(|(r, e)| (r, SearchTerm::Expr(e)))
+}
+
+/// `-term`, `NOT term` or a plain term
+fn unary(input: &str) -> PResult<'_, SearchExpr> {
+    let negated = if input.starts_with('-') {
+        preceded(tag("-"), cut(context("search term", atom)))(input)
+    } else if input.starts_with("NOT") {
+        match preceded(terminated(tag("NOT"), space1), cut(context("search term", unary)))(input) {
+            // `NOTE` is a word
+            Err(Err::Error(_)) => return atom(input),
+            r => r,
         }
+    } else {
+        return atom(input);
+    };
+    negated.map(|(r, e)| (r, SearchExpr::Not(Box::new(e))))
+}
+
+/// A parenthesized group, a `#tag`, a priority, a due date filter or words,
+/// told apart by how they start so a plain word doesn't go through every alternative
+fn atom(input: &str) -> PResult<'_, SearchExpr> {
+    match input.chars().next() {
+        Some('(') => delimited(
+            terminated(tag("("), space0),
+            cut(context("search term", |i| or_expr(i, false))),
+            cut(preceded(space0, context("closing parenthesis", tag(")")))),
+        )(input).map(|(r, (e, _))| (r, e)),
+        Some('#') => todo_tag(input).map(|(r, t)| (r, SearchExpr::Tag(Tag::new(t)))),
+        Some('!') => priority(input).map(|(r, p)| (r, SearchExpr::Priority(p))),
+        _ if input.starts_with("due:") => due_filter(input),
+        _ => match verify(word, |w: &str| w == "overdue")(input) {
+            Ok((r, _)) => Ok((r, SearchExpr::Overdue)),
+            Err(_) => search_words(input),
+        },
     }
-    Query::Search(SearchParams { words, tags })
+}
+
+/// `due:` with an optional `<`, `<=`, `>=` or `>`, then a date or `today`
+fn due_filter(input: &str) -> PResult<'_, SearchExpr> {
+    let op = alt((
+        |i| tag("<=")(i).map(|(r, _)| (r, DateOp::OnOrBefore)),
+        |i| tag(">=")(i).map(|(r, _)| (r, DateOp::OnOrAfter)),
+        |i| tag("<")(i).map(|(r, _)| (r, DateOp::Before)),
+        |i| tag(">")(i).map(|(r, _)| (r, DateOp::After)),
+        |i| Ok((i, DateOp::On)),
+    ));
+    let spec = alt((
+        |i| tag("today")(i).map(|(r, _)| (r, DateSpec::Today)),
+        |i| date_literal(i).map(|(r, d)| (r, DateSpec::Date(d))),
+    ));
+    preceded(tag("due:"), cut(context("date (YYYY-MM-DD or today)", pair(op, spec))))(input)
+        .map(|(r, (op, spec))| (r, SearchExpr::Due(op, spec)))
+}
+
+/// Description text up to a space or parenthesis that isn't an operator keyword and doesn't start
+/// like a negation or a modifier, which only the top level takes. It is split into words the way descriptions are, so `v2.1` finds `v2` and `1`.
+fn search_words(input: &str) -> PResult<'_, SearchExpr> {
+    let text = take_while1(|c: char| is_text_char(c) && !matches!(c, ' ' | '\t' | '(' | ')'));
+    let text = verify(text, |w: &str| {
+        !w.starts_with('-') && !KEYWORDS.contains(&w) && !MODIFIERS.iter().any(|m| w.starts_with(m))
+    });
+    map_res(text, |w: &str| {
+        let words: Vec<_> = Description::new(w).words().map(|w| SearchExpr::Word(SearchWord::new(w))).collect();
+        if words.is_empty() { Err(()) } else { Ok(SearchExpr::and(words)) }
+    })(input)
+}
+
+/// A word that `search_words` would take as it is: no operator, keyword or leading `-`
+fn is_plain_word(w: &str) -> bool {
+    w.chars().all(is_word_char) && w.chars().any(char::is_alphanumeric) && !w.starts_with('-') && !KEYWORDS.contains(&w)
+}
+
+/// `--open`, `--done`, `--all` or `--by-priority`, short for `sort:priority`
+fn search_flag(input: &str) -> PResult<'_, Flags> {
+    preceded(tag("--"), cut(context("search flag (--open, --done, --all, --by-priority)", map_res(word, |w| {
+        let status = |s| Ok(Flags { status: Some(s), ..Flags::default() });
+        match w {
+            "open" => status(StatusFilter::Open),
+            "done" => status(StatusFilter::Done),
+            "all" => status(StatusFilter::All),
+            "by-priority" => Ok(Flags { sort: Some(Sort::by(SortKey::Priority)), ..Flags::default() }),
+            _ => Err(()),
+        }
+    }))))(input)
+}
+
+/// `sort:<key>[:asc|:desc]`, `limit:<n>` or `offset:<n>`
+fn search_modifier(input: &str) -> PResult<'_, Flags> {
+    alt((
+        |i| sort_modifier(i).map(|(r, sort)| (r, Flags { sort: Some(sort), ..Flags::default() })),
+        |i| preceded(tag("limit:"), cut(number))(i).map(|(r, n)| (r, Flags { limit: Some(n), ..Flags::default() })),
+        |i| preceded(tag("offset:"), cut(number))(i).map(|(r, n)| (r, Flags { offset: Some(n), ..Flags::default() })),
+    ))(input)
+}
+
+fn number(input: &str) -> PResult<'_, usize> {
+    context("count", map_res(digit1, str::parse))(input)
+}
+
+/// `sort:<key>` with an optional `:asc` or `:desc`; the key picks the direction when it is left out
+fn sort_modifier(input: &str) -> PResult<'_, Sort> {
+    let key = context("sort key (index, relevance, priority, due, description)", map_res(word, str::parse::<SortKey>));
+    let direction = preceded(tag(":"), cut(context("sort direction (asc or desc)", map_res(word, str::parse))));
+    preceded(tag("sort:"), cut(pair(key, opt(direction))))(input)
+        .map(|(r, (key, direction))| (r, Sort { key, direction: direction.unwrap_or_else(|| key.default_direction()) }))
 }
@@ -0,0 +1,115 @@
+//! Newline-delimited JSON commands, an alternative to the text grammar for generated input:
+//! `{"op":"add","description":"say \"hi\"","tags":["x"],"due":"2026-11-01"}`, `{"op":"done","index":3}`,
+//! `{"op":"prio","index":3,"priority":"high"}`, `{"op":"search","query":"milk OR bread --all"}`, `{"op":"count","query":"#work"}`.
+use serde::Deserialize;
+
+use super::ParseError;
+use crate::{is_tag_name, Description, Index, Priority, Query, Tag};
+
+#[derive(Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase", deny_unknown_fields)]
+enum JsonCommand {
+    Add {
+        description: String,
+        #[serde(default)]
+        tags: Vec<String>,
+        /// `YYYY-MM-DD`
+        due: Option<String>,
+        priority: Option<String>,
+    },
+    Done { index: u64 },
+    Undone { index: u64 },
+    Edit {
+        index: u64,
+        description: Option<String>,
+        tags: Option<Vec<String>>,
+    },
+    Delete { index: u64 },
+    /// A missing or null `priority` clears it
+    Prio {
+        index: u64,
+        priority: Option<String>,
+    },
+    Search {
+        // Uses the text search syntax, which has no quoting to get wrong
+        #[serde(default)]
+        query: String,
+    },
+    Count {
+        #[serde(default)]
+        query: String,
+    },
+}
+
+/// Parses one JSON command into a `Query`, applying the same rules as the text grammar.
+pub(super) fn query(input: &str) -> Result<Query, ParseError> {
+    let command: JsonCommand = serde_json::from_str(input).map_err(|e| {
+        let location = format!(" at line {} column {}", e.line(), e.column());
+        let message = e.to_string();
+        let message = message.strip_suffix(&location).unwrap_or(&message);
+        let mut offset = e.column().saturating_sub(1).min(input.len());
+        while !input.is_char_boundary(offset) { offset -= 1; }
+        ParseError::at(input, &input[offset..], &format!("JSON command ({message})"))
+    })?;
+
+    Ok(match command {
+        JsonCommand::Add { description, tags, due, priority } => Query::Add(
+            checked_description(input, &description)?,
+            checked_tags(input, &tags)?,
+            due.map(|d| d.parse().map_err(|_| field_error(input, "due", "date (YYYY-MM-DD)"))).transpose()?,
+            priority.map(|p| checked_priority(input, &p)).transpose()?,
+        ),
+        JsonCommand::Done { index } => Query::Done(Index::new(index)),
+        JsonCommand::Undone { index } => Query::Undone(Index::new(index)),
+        JsonCommand::Edit { description: None, tags: None, .. } => {
+            return Err(ParseError::at(input, "", "`description` or `tags` field"));
+        }
+        JsonCommand::Edit { index, description, tags } => Query::Edit(
+            Index::new(index),
+            description.map(|d| checked_description(input, &d)).transpose()?,
+            tags.map(|t| checked_tags(input, &t)).transpose()?,
+        ),
+        JsonCommand::Delete { index } => Query::Delete(Index::new(index)),
+        JsonCommand::Prio { index, priority } => Query::Prio(Index::new(index), priority.map(|p| checked_priority(input, &p)).transpose()?),
+        JsonCommand::Search { query } => text_query("search", &query)?,
+        JsonCommand::Count { query } => text_query("count", &query)?,
+    })
+}
+
+/// Parses `query` as the text command `<command> <query>`, reporting errors against `query` itself
+fn text_query(command: &str, query: &str) -> Result<Query, ParseError> {
+    super::query(&format!("{command} {query}")).map_err(|mut e| {
+        e.position = e.position.saturating_sub(command.len() + 1);
+        e.expected = format!("{} in `query`", e.expected);
+        e
+    })
+}
+
+/// An error pointing at the `"field"` key of the command
+fn field_error(input: &str, field: &str, expected: &str) -> ParseError {
+    let offset = input.find(&format!("\"{field}\"")).unwrap_or(0);
+    ParseError::at(input, &input[offset..], expected)
+}
+
+fn checked_description(input: &str, description: &str) -> Result<Description, ParseError> {
+    if description.chars().any(|c| c.is_control() && c != '\t') {
+        return Err(field_error(input, "description", "description without control characters"));
+    }
+    Ok(Description::new(description))
+}
+
+fn checked_priority(input: &str, priority: &str) -> Result<Priority, ParseError> {
+    priority.parse().map_err(|_| field_error(input, "priority", "priority (low, medium or high)"))
+}
+
+fn checked_tags(input: &str, tags: &[String]) -> Result<Vec<Tag>, ParseError> {
+    tags.iter()
+        .map(|t| {
+            let t = t.strip_prefix('#').unwrap_or(t);
+            if !is_tag_name(t) {
+                return Err(field_error(input, "tags", "tags made of letters, digits and `-`"));
+            }
+            Ok(Tag::new(t))
+        })
+        .collect()
+}
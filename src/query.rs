@@ -1,17 +1,181 @@
 use std::fmt::{self, Display};
-use crate::{Description, Index, Tag};
+use std::str::FromStr;
+use crate::{Date, Description, Index, Priority, Tag};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Query {
-    Add(Description, Vec<Tag>),
+    /// New item with its tags, optional due date and optional priority
+    Add(Description, Vec<Tag>, Option<Date>, Option<Priority>),
     Done(Index),
+    Undone(Index),
+    /// Replace the description and/or tags; `None` keeps the current value
+    Edit(Index, Option<Description>, Option<Vec<Tag>>),
+    Delete(Index),
+    /// Set or clear (`None`) an item's priority
+    Prio(Index, Option<Priority>),
     Search(SearchParams),
+    /// How many items match, without listing them; sorting and paging don't apply
+    Count(SearchParams),
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Default)]
 pub struct SearchParams {
-    pub words: Vec<SearchWord>,
-    pub tags: Vec<Tag>,
+    pub expr: SearchExpr,
+    pub status: StatusFilter,
+    pub sort: Sort,
+    /// Most items to return; `None` for all of them
+    pub limit: Option<usize>,
+    /// Matching items to skip before the first one returned
+    pub offset: usize,
+}
+
+/// Order of search results: a key and a direction. Ties always go by ascending index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Sort {
+    pub key: SortKey,
+    pub direction: SortDirection,
+}
+
+impl Sort {
+    /// Sorts by `key` in the direction that reads most naturally for it
+    #[must_use] pub fn by(key: SortKey) -> Self {
+        Self { key, direction: key.default_direction() }
+    }
+}
+
+/// What search results are ordered by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortKey {
+    #[default]
+    Index,
+    /// How well the item matches the search's words and tags
+    Relevance,
+    /// Items without a priority come last in either direction
+    Priority,
+    /// Items without a due date come last in either direction
+    Due,
+    /// Case-insensitive, by character
+    Description,
+}
+
+impl SortKey {
+    /// Oldest, best-matching, most urgent, soonest and alphabetical first
+    #[must_use] pub fn default_direction(self) -> SortDirection {
+        match self {
+            Self::Relevance | Self::Priority => SortDirection::Desc,
+            Self::Index | Self::Due | Self::Description => SortDirection::Asc,
+        }
+    }
+}
+
+impl FromStr for SortKey {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "index" => Ok(Self::Index),
+            "relevance" => Ok(Self::Relevance),
+            "priority" => Ok(Self::Priority),
+            "due" => Ok(Self::Due),
+            "description" => Ok(Self::Description),
+            _ => Err(format!("unknown sort key `{s}` (expected index, relevance, priority, due or description)")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortDirection {
+    #[default]
+    Asc,
+    Desc,
+}
+
+impl FromStr for SortDirection {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "asc" => Ok(Self::Asc),
+            "desc" => Ok(Self::Desc),
+            _ => Err(format!("unknown sort direction `{s}` (expected asc or desc)")),
+        }
+    }
+}
+
+/// Boolean search expression; words and tags match by subsequence
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SearchExpr {
+    Word(SearchWord),
+    Tag(Tag),
+    /// Matches everything when empty
+    And(Vec<SearchExpr>),
+    Or(Vec<SearchExpr>),
+    Not(Box<SearchExpr>),
+    /// Items whose due date compares as given; items without one never match
+    Due(DateOp, DateSpec),
+    /// Items with exactly this priority
+    Priority(Priority),
+    /// Open items due before today
+    Overdue,
+}
+
+impl SearchExpr {
+    /// Conjunction that collapses to its only operand
+    #[must_use] pub fn and(mut exprs: Vec<SearchExpr>) -> Self {
+        if exprs.len() == 1 { exprs.remove(0) } else { Self::And(exprs) }
+    }
+
+    /// Disjunction that collapses to its only operand
+    #[must_use] pub fn or(mut exprs: Vec<SearchExpr>) -> Self {
+        if exprs.len() == 1 { exprs.remove(0) } else { Self::Or(exprs) }
+    }
+}
+
+impl Default for SearchExpr {
+    fn default() -> Self { Self::And(Vec::new()) }
+}
+
+/// How `due:` compares an item's due date with the searched one: `<`, `<=`, none, `>=` or `>`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateOp {
+    Before,
+    OnOrBefore,
+    On,
+    OnOrAfter,
+    After,
+}
+
+/// A date in a search; `Today` is resolved with the list's clock when the search runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateSpec {
+    Today,
+    Date(Date),
+}
+
+impl DateSpec {
+    #[must_use] pub fn resolve(self, today: Date) -> Date {
+        match self {
+            Self::Today => today,
+            Self::Date(d) => d,
+        }
+    }
+}
+
+/// Which items a search considers, by completion state
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StatusFilter {
+    #[default]
+    Open,
+    Done,
+    All,
+}
+
+impl StatusFilter {
+    #[must_use] pub fn matches(self, done: bool) -> bool {
+        match self {
+            Self::Open => !done,
+            Self::Done => done,
+            Self::All => true,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -21,11 +185,44 @@ impl SearchWord {
     #[must_use] pub fn new(s: &str) -> Self { Self(s.to_owned()) }
 }
 
+/// Machine-readable category of a `QueryError`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    /// The line is not a valid query
+    Parse,
+    /// No live item has the given index
+    NotFound,
+    /// `undone` on an item that is still open
+    NotDone,
+    /// The change was applied but could not be persisted
+    Storage,
+}
+
+impl ErrorCode {
+    #[must_use] pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Parse => "parse_error",
+            Self::NotFound => "not_found",
+            Self::NotDone => "not_done",
+            Self::Storage => "storage_error",
+        }
+    }
+}
+
 #[derive(Debug)]
-pub struct QueryError(pub String);
+pub struct QueryError {
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+impl QueryError {
+    #[must_use] pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self { code, message: message.into() }
+    }
+}
 
 impl Display for QueryError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Error: {}", self.0)
+        write!(f, "Error: {}", self.message)
     }
 }
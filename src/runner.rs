@@ -1,64 +1,202 @@
 use std::io::Write;
-use crate::{parser, Query, QueryError, TodoList, TodoItem, Index};
+use std::str::FromStr;
+use crate::{parser, storage::Journal, ErrorCode, Query, QueryError, SearchPage, TodoList, TodoItem, Index};
 
-pub fn run_line_buffered<W: Write>(line: &str, tl: &mut TodoList, out: &mut W, line_ending: &[u8]) {
+/// How results and errors are written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    /// `N item(s) found` followed by `idx "desc" #tag` lines
+    #[default]
+    Text,
+    /// One JSON object per result, e.g. `{"type":"added","index":3}`
+    JsonLines,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::JsonLines),
+            _ => Err(format!("unknown output format `{s}` (expected text or json)")),
+        }
+    }
+}
+
+/// Runs a single query line and writes its result, or an `Error: ...` line, to `out`.
+/// # Errors
+/// Returns the error that was reported, so callers can stop at the first one.
+pub fn run_line_buffered<W: Write>(line: &str, tl: &mut TodoList, out: &mut W, line_ending: &[u8]) -> Result<(), QueryError> {
+    run_line_journaled(line, tl, None, out, line_ending)
+}
+
+/// Same as `run_line_buffered`, but successfully applied mutations are also appended to `journal`
+/// # Errors
+/// Returns the error that was reported, so callers can stop at the first one.
+pub fn run_line_journaled<W: Write>(line: &str, tl: &mut TodoList, journal: Option<&mut Journal>, out: &mut W, line_ending: &[u8]) -> Result<(), QueryError> {
+    run_line_formatted(line, tl, journal, out, line_ending, OutputFormat::Text)
+}
+
+/// Same as `run_line_journaled`, writing the result (or error) in the given `format`
+/// # Errors
+/// Returns the error that was reported, so callers can stop at the first one.
+pub fn run_line_formatted<W: Write>(
+    line: &str,
+    tl: &mut TodoList,
+    journal: Option<&mut Journal>,
+    out: &mut W,
+    line_ending: &[u8],
+    format: OutputFormat,
+) -> Result<(), QueryError> {
     let trimmed = line.trim();
-    if trimmed.is_empty() { return; }
-    
-    if let Ok((_, q)) = parser::query(trimmed) {
-        match run_query_ref(q, tl) {
-            Ok(r) => { let _ = write_result(out, &r, line_ending); }
-            Err(e) => { 
-                let _ = write!(out, "Error: {}", e.0);
-                let _ = out.write_all(line_ending);
+    if trimmed.is_empty() { return Ok(()); }
+
+    let result = parser::query(trimmed)
+        .map_err(|e| QueryError::new(ErrorCode::Parse, e.to_string()))
+        .and_then(|q| {
+            let mut journal = journal;
+            // Encoded up front, so a record the journal refuses leaves the list unchanged
+            if let Some(j) = journal.as_deref_mut() {
+                j.prepare(&q).map_err(journal_error)?;
             }
-        }
+            let r = run_query_ref(q, tl)?;
+            if let Some(j) = journal {
+                j.commit().map_err(journal_error)?;
+            }
+            let _ = match format {
+                OutputFormat::Text => write_result(out, &r, line_ending),
+                OutputFormat::JsonLines => write_json_result(out, &r, line_ending),
+            };
+            Ok(())
+        });
+    if let Err(e) = &result {
+        let _ = match format {
+            OutputFormat::Text => out.write_all(b"Error: ").and_then(|()| out.write_all(e.message.as_bytes())),
+            OutputFormat::JsonLines => write_json_error(out, e),
+        };
+        let _ = out.write_all(line_ending);
     }
+    result
 }
 
 enum QueryResultRef<'a> {
     Added(Index),
-    Done,
-    Found(Vec<&'a TodoItem>, bool), // bool indicates concise mode
+    Done(Index),
+    Undone(Index),
+    Edited(Index),
+    Deleted(Index),
+    Prioritized(Index),
+    Counted(usize),
+    Found(SearchPage<&'a TodoItem>, bool), // bool indicates concise mode
+    /// Found items with their relevance scores
+    Scored(SearchPage<(&'a TodoItem, u32)>, bool),
+}
+
+fn journal_error(e: std::io::Error) -> QueryError {
+    QueryError::new(ErrorCode::Storage, format!("journal write failed: {e}"))
+}
+
+fn not_found(idx: Index) -> QueryError {
+    QueryError::new(ErrorCode::NotFound, format!("Index {idx} not found"))
 }
 
 fn run_query_ref(q: Query, tl: &mut TodoList) -> Result<QueryResultRef<'_>, QueryError> {
     let concise = tl.is_concise();
     match q {
-        Query::Add(desc, tags) => Ok(QueryResultRef::Added(tl.push(desc, tags))),
+        Query::Add(desc, tags, due, priority) => {
+            let idx = tl.push(desc, tags);
+            if due.is_some() { tl.set_due(idx, due); }
+            if priority.is_some() { tl.set_priority(idx, priority); }
+            Ok(QueryResultRef::Added(idx))
+        }
         Query::Done(idx) => tl.done_with_index(idx)
-            .map(|_| QueryResultRef::Done)
-            .ok_or_else(|| QueryError(format!("Index {idx} not found"))),
-        Query::Search(params) => Ok(QueryResultRef::Found(tl.search(&params), concise)),
+            .map(QueryResultRef::Done)
+            .ok_or_else(|| not_found(idx)),
+        Query::Undone(idx) => match tl.undone_with_index(idx) {
+            Some(_) => Ok(QueryResultRef::Undone(idx)),
+            None if tl.get(idx).is_some() => Err(QueryError::new(ErrorCode::NotDone, format!("Index {idx} is not done"))),
+            None => Err(not_found(idx)),
+        },
+        Query::Edit(idx, desc, tags) => tl.edit(idx, desc, tags)
+            .map(QueryResultRef::Edited)
+            .ok_or_else(|| not_found(idx)),
+        Query::Delete(idx) => tl.remove(idx)
+            .map(|_| QueryResultRef::Deleted(idx))
+            .ok_or_else(|| not_found(idx)),
+        Query::Prio(idx, priority) => tl.set_priority(idx, priority)
+            .map(QueryResultRef::Prioritized)
+            .ok_or_else(|| not_found(idx)),
+        Query::Search(params) if tl.shows_scores() => Ok(QueryResultRef::Scored(tl.search_scored(&params), concise)),
+        Query::Search(params) => Ok(QueryResultRef::Found(tl.search_page(&params), concise)),
+        Query::Count(params) => Ok(QueryResultRef::Counted(tl.count(&params))),
     }
 }
 
 fn write_result<W: Write>(out: &mut W, r: &QueryResultRef, line_ending: &[u8]) -> std::io::Result<()> {
     match r {
+        // Scores only show up in JSON output
+        QueryResultRef::Scored(scored, concise) => {
+            let items = scored.items.iter().map(|(item, _)| *item).collect();
+            write_result(out, &QueryResultRef::Found(SearchPage { items, total: scored.total }, *concise), line_ending)
+        }
         QueryResultRef::Added(idx) => {
             let mut buffer = itoa::Buffer::new();
             out.write_all(buffer.format(idx.0).as_bytes())?;
             out.write_all(line_ending)
         }
-        QueryResultRef::Done => {
+        QueryResultRef::Done(_) => {
             out.write_all(b"done")?;
             out.write_all(line_ending)
         }
-        QueryResultRef::Found(items, concise) => {
+        QueryResultRef::Undone(_) => {
+            out.write_all(b"undone")?;
+            out.write_all(line_ending)
+        }
+        QueryResultRef::Edited(_) => {
+            out.write_all(b"edited")?;
+            out.write_all(line_ending)
+        }
+        QueryResultRef::Deleted(_) => {
+            out.write_all(b"deleted")?;
+            out.write_all(line_ending)
+        }
+        QueryResultRef::Prioritized(_) => {
+            out.write_all(b"prioritized")?;
+            out.write_all(line_ending)
+        }
+        QueryResultRef::Counted(n) => {
+            let mut buffer = itoa::Buffer::new();
+            out.write_all(buffer.format(*n).as_bytes())?;
+            out.write_all(line_ending)
+        }
+        QueryResultRef::Found(page, concise) => {
             let mut buffer = itoa::Buffer::new();
-            out.write_all(buffer.format(items.len()).as_bytes())?;
+            out.write_all(buffer.format(page.items.len()).as_bytes())?;
+            // A partial page also says how many items match in all
+            if page.total != page.items.len() {
+                out.write_all(b" of ")?;
+                out.write_all(buffer.format(page.total).as_bytes())?;
+            }
             out.write_all(b" item(s) found")?;
             out.write_all(line_ending)?;
-            for item in items.iter() {
+            for item in page.items.iter() {
                 out.write_all(buffer.format(item.index.0).as_bytes())?;
                 if !concise {
                     out.write_all(b" \"")?;
-                    out.write_all(item.description.0.as_bytes())?;
+                    out.write_all(item.description.escaped().as_bytes())?;
                     out.write_all(b"\"")?;
                     for tag in &item.tags {
                         out.write_all(b" #")?;
                         out.write_all(tag.0.as_bytes())?;
                     }
+                    if let Some(due) = item.due {
+                        write!(out, " due:{due}")?;
+                    }
+                }
+                // Concise output keeps the priority, since it decides what to do first
+                if let Some(priority) = item.priority {
+                    out.write_all(b" !")?;
+                    out.write_all(priority.as_str().as_bytes())?;
                 }
                 out.write_all(line_ending)?;
             }
@@ -66,3 +204,116 @@ fn write_result<W: Write>(out: &mut W, r: &QueryResultRef, line_ending: &[u8]) -
         }
     }
 }
+
+fn write_json_result<W: Write>(out: &mut W, r: &QueryResultRef, line_ending: &[u8]) -> std::io::Result<()> {
+    let mut buffer = itoa::Buffer::new();
+    let (kind, idx) = match r {
+        QueryResultRef::Added(idx) => ("added", idx),
+        QueryResultRef::Done(idx) => ("done", idx),
+        QueryResultRef::Undone(idx) => ("undone", idx),
+        QueryResultRef::Edited(idx) => ("edited", idx),
+        QueryResultRef::Deleted(idx) => ("deleted", idx),
+        QueryResultRef::Prioritized(idx) => ("prioritized", idx),
+        QueryResultRef::Counted(n) => {
+            out.write_all(b"{\"type\":\"counted\",\"count\":")?;
+            out.write_all(buffer.format(*n).as_bytes())?;
+            out.write_all(b"}")?;
+            return out.write_all(line_ending);
+        }
+        QueryResultRef::Found(page, concise) => {
+            let items = page.items.iter().map(|item| (*item, None));
+            return write_json_items(out, items, page.total, *concise, line_ending);
+        }
+        QueryResultRef::Scored(scored, concise) => {
+            let items = scored.items.iter().map(|(item, score)| (*item, Some(*score)));
+            return write_json_items(out, items, scored.total, *concise, line_ending);
+        }
+    };
+    out.write_all(b"{\"type\":\"")?;
+    out.write_all(kind.as_bytes())?;
+    out.write_all(b"\",\"index\":")?;
+    out.write_all(buffer.format(idx.0).as_bytes())?;
+    out.write_all(b"}")?;
+    out.write_all(line_ending)
+}
+
+/// The `found` object: the page's `count` and `items`, with a `score` on each item that has one,
+/// and the `total` number of matching items
+fn write_json_items<'a, W: Write>(
+    out: &mut W,
+    items: impl ExactSizeIterator<Item = (&'a TodoItem, Option<u32>)>,
+    total: usize,
+    concise: bool,
+    line_ending: &[u8],
+) -> std::io::Result<()> {
+    let mut buffer = itoa::Buffer::new();
+    out.write_all(b"{\"type\":\"found\",\"count\":")?;
+    out.write_all(buffer.format(items.len()).as_bytes())?;
+    out.write_all(b",\"total\":")?;
+    out.write_all(buffer.format(total).as_bytes())?;
+    out.write_all(b",\"items\":[")?;
+    for (i, (item, score)) in items.enumerate() {
+        if i > 0 { out.write_all(b",")?; }
+        out.write_all(b"{\"index\":")?;
+        out.write_all(buffer.format(item.index.0).as_bytes())?;
+        // Concise mode keeps just the indices
+        if !concise {
+            out.write_all(b",\"description\":")?;
+            write_json_str(out, &item.description.0)?;
+            out.write_all(b",\"tags\":[")?;
+            for (j, tag) in item.tags.iter().enumerate() {
+                if j > 0 { out.write_all(b",")?; }
+                write_json_str(out, &tag.0)?;
+            }
+            out.write_all(if item.done { b"],\"done\":true" } else { b"],\"done\":false" })?;
+            if let Some(due) = item.due {
+                write!(out, ",\"due\":\"{due}\"")?;
+            }
+        }
+        if let Some(priority) = item.priority {
+            write!(out, ",\"priority\":\"{priority}\"")?;
+        }
+        if let Some(score) = score {
+            out.write_all(b",\"score\":")?;
+            out.write_all(buffer.format(score).as_bytes())?;
+        }
+        out.write_all(b"}")?;
+    }
+    out.write_all(b"]}")?;
+    out.write_all(line_ending)
+}
+
+fn write_json_error<W: Write>(out: &mut W, e: &QueryError) -> std::io::Result<()> {
+    out.write_all(b"{\"type\":\"error\",\"code\":\"")?;
+    out.write_all(e.code.as_str().as_bytes())?;
+    out.write_all(b"\",\"message\":")?;
+    write_json_str(out, &e.message)?;
+    out.write_all(b"}")
+}
+
+/// Writes `s` as a JSON string literal
+fn write_json_str<W: Write>(out: &mut W, s: &str) -> std::io::Result<()> {
+    out.write_all(b"\"")?;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        let escape: &[u8] = match c {
+            '"' => b"\\\"",
+            '\\' => b"\\\\",
+            '\n' => b"\\n",
+            '\r' => b"\\r",
+            '\t' => b"\\t",
+            c if (c as u32) < 0x20 => {
+                out.write_all(&s.as_bytes()[start..i])?;
+                write!(out, "\\u{:04x}", c as u32)?;
+                start = i + 1;
+                continue;
+            }
+            _ => continue,
+        };
+        out.write_all(&s.as_bytes()[start..i])?;
+        out.write_all(escape)?;
+        start = i + c.len_utf8();
+    }
+    out.write_all(&s.as_bytes()[start..])?;
+    out.write_all(b"\"")
+}
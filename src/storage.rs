@@ -0,0 +1,524 @@
+//! Append-only journal of mutating queries.
+//!
+//! Each record is framed as `[len: u32][checksum: u32][payload]`, so a torn
+//! write at the tail is detected on replay and cut off instead of being applied.
+//! A bad record that is followed by more data is corruption rather than a torn
+//! write, and the journal refuses to open instead of dropping what comes after it.
+//!
+//! `checkpoint` saves the list as a snapshot next to the journal, marked with the
+//! journal position it holds; `load` starts from that snapshot and replays only the
+//! records after it.
+//!
+//! A writer holds an exclusive lock on `<path>.lock` for as long as the journal is open,
+//! so a second writer is refused. The journal itself is only share-locked, so read-only
+//! opens work next to a writer; they never truncate, append or checkpoint, and treat a
+//! record the writer has not finished yet like a torn tail.
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::codec::{self, Reader};
+use crate::{Date, Description, Index, JournalMark, Metadata, Query, Tag, TodoList};
+
+const MAGIC: &[u8; 8] = b"TODOJRN1";
+const FRAME_LEN: usize = 8;
+/// Upper bound on a single record; anything larger is treated as a bad record
+const MAX_RECORD_LEN: usize = 64 << 20;
+
+const RECORD_ADD: u8 = 1;
+const RECORD_DONE: u8 = 2;
+const RECORD_UNDONE: u8 = 3;
+const RECORD_EDIT: u8 = 4;
+const RECORD_DELETE: u8 = 5;
+const RECORD_META: u8 = 6;
+const RECORD_PRIO: u8 = 7;
+
+enum Record {
+    Query(Query),
+    Meta(Index, Metadata),
+}
+
+pub struct Journal {
+    file: File,
+    path: PathBuf,
+    buf: Vec<u8>,
+    discarded: u64,
+    /// Length of the journal up to the end of the last complete record
+    len: u64,
+    /// Frame header of the last complete record; zero while there is none
+    last_frame: [u8; FRAME_LEN],
+    /// Records replayed or appended since the snapshot the list was loaded from
+    since_checkpoint: u64,
+    /// Set when a torn frame could not be cut off; further appends would land behind it
+    failed: bool,
+    /// Set when an append failed, so the caller's list holds a change the journal lacks
+    lost_write: bool,
+    /// The writer lock on `<path>.lock`; `None` for read-only journals
+    writer_lock: Option<File>,
+}
+
+impl Journal {
+    /// Opens (or creates) the journal at `path` and replays its records into `tl`.
+    /// An incomplete or corrupt record that runs to the end of the file is truncated away.
+    /// # Errors
+    /// Returns an I/O error if the file cannot be opened or has a foreign header,
+    /// `WouldBlock` if another writer holds it open, and `InvalidData` if a record
+    /// before the last one is corrupt.
+    pub fn open<P: AsRef<Path>>(path: P, tl: &mut TodoList) -> io::Result<Self> {
+        Self::open_with(path.as_ref(), tl, None, true)
+    }
+
+    /// Opens the journal at `path` for reading only and replays its records into `tl`.
+    /// Works while a writer has the journal open; an incomplete record at the end is skipped
+    /// and left in place, and appends and checkpoints are refused.
+    /// # Errors
+    /// Returns `NotFound` if the file does not exist, and otherwise the same errors as
+    /// [`Journal::open`], except that a writer holding the journal is not one.
+    pub fn open_read_only<P: AsRef<Path>>(path: P, tl: &mut TodoList) -> io::Result<Self> {
+        Self::open_with(path.as_ref(), tl, None, false)
+    }
+
+    /// Opens (or creates) the journal at `path` and returns the list it holds. Starts from the
+    /// snapshot written by [`Journal::checkpoint`] when the journal still holds what it was taken
+    /// from, replaying only the later records; a missing, outdated or damaged snapshot only costs
+    /// a full replay.
+    /// # Errors
+    /// Returns the same errors as [`Journal::open`].
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<(TodoList, Self)> {
+        Self::load_with(path.as_ref(), true)
+    }
+
+    /// Like [`Journal::load`], but opened like [`Journal::open_read_only`]
+    /// # Errors
+    /// Returns the same errors as [`Journal::open_read_only`].
+    pub fn load_read_only<P: AsRef<Path>>(path: P) -> io::Result<(TodoList, Self)> {
+        Self::load_with(path.as_ref(), false)
+    }
+
+    fn load_with(path: &Path, write: bool) -> io::Result<(TodoList, Self)> {
+        let (mut tl, mark) = TodoList::load_snapshot_marked(&snapshot_path(path)).unwrap_or_default();
+        let journal = Self::open_with(path, &mut tl, Some(mark), write)?;
+        Ok((tl, journal))
+    }
+
+    /// Opens the journal and replays it into `tl`, which holds the journal up to `mark` if given.
+    /// When the journal no longer holds what `mark` describes, `tl` is cleared and fully replayed.
+    fn open_with(path: &Path, tl: &mut TodoList, mark: Option<JournalMark>, write: bool) -> io::Result<Self> {
+        // Each writer appends at the end it replayed to, so a second one would overwrite records
+        let writer_lock = if write {
+            let lock = OpenOptions::new().write(true).create(true).truncate(false).open(lock_path(path))?;
+            lock.try_lock().map_err(|e| match e {
+                TryLockError::WouldBlock => io::Error::new(io::ErrorKind::WouldBlock, "journal is in use by another process"),
+                TryLockError::Error(e) => e,
+            })?;
+            Some(lock)
+        } else {
+            None
+        };
+        let mut file = OpenOptions::new().read(true).write(write).create(write).truncate(false).open(path)?;
+        file.try_lock_shared().map_err(|e| match e {
+            TryLockError::WouldBlock => io::Error::new(io::ErrorKind::WouldBlock, "journal is locked by another process"),
+            TryLockError::Error(e) => e,
+        })?;
+        let file_len = file.metadata()?.len();
+
+        if file_len < MAGIC.len() as u64 {
+            let mut head = Vec::new();
+            file.read_to_end(&mut head)?;
+            if !MAGIC.starts_with(&head) {
+                return Err(not_a_journal());
+            }
+            if mark.is_some() { *tl = TodoList::new(); }
+            if !write {
+                return Ok(Self::new(file, path, file_len, file_len, [0; FRAME_LEN], 0, None));
+            }
+            // Empty, or crashed while writing the header: start over
+            file.set_len(0)?;
+            file.seek(SeekFrom::Start(0))?;
+            file.write_all(MAGIC)?;
+            return Ok(Self::new(file, path, file_len, MAGIC.len() as u64, [0; FRAME_LEN], 0, writer_lock));
+        }
+
+        let start = match mark {
+            Some(mark) if holds(&mut file, file_len, mark)? => mark,
+            Some(_) => {
+                *tl = TodoList::new();
+                JournalMark { len: MAGIC.len() as u64, last_frame: [0; FRAME_LEN] }
+            }
+            None => JournalMark { len: MAGIC.len() as u64, last_frame: [0; FRAME_LEN] },
+        };
+        let (end, records) = replay(&mut file, start, file_len, tl)?;
+        if write {
+            if end.len < file_len {
+                file.set_len(end.len)?;
+            }
+            file.seek(SeekFrom::Start(end.len))?;
+        }
+        Ok(Self::new(file, path, file_len - end.len, end.len, end.last_frame, records, writer_lock))
+    }
+
+    fn new(file: File, path: &Path, discarded: u64, len: u64, last_frame: [u8; FRAME_LEN], since_checkpoint: u64, writer_lock: Option<File>) -> Self {
+        Self {
+            file,
+            path: path.to_owned(),
+            buf: Vec::new(),
+            discarded,
+            len,
+            last_frame,
+            since_checkpoint,
+            failed: false,
+            lost_write: false,
+            writer_lock,
+        }
+    }
+
+    /// Whether the journal was opened with [`Journal::open_read_only`] or [`Journal::load_read_only`]
+    #[must_use] pub fn is_read_only(&self) -> bool { self.writer_lock.is_none() }
+
+    /// Number of trailing bytes dropped during replay because they did not form a complete record;
+    /// a read-only journal skips them but leaves them in the file
+    #[must_use] pub fn discarded_bytes(&self) -> u64 { self.discarded }
+
+    /// Number of records a `load` would replay on top of the last checkpoint
+    #[must_use] pub fn records_since_checkpoint(&self) -> u64 { self.since_checkpoint }
+
+    /// Saves `tl` as a snapshot next to the journal (`<path>.snap`), so the next
+    /// [`Journal::load`] replays only the records appended after this point.
+    /// `tl` must be the list the journal's records build.
+    /// # Errors
+    /// Returns an I/O error if the snapshot cannot be written, or if an earlier append
+    /// failed and `tl` holds a change the journal lacks.
+    pub fn checkpoint(&mut self, tl: &TodoList) -> io::Result<()> {
+        self.check_writable()?;
+        if self.lost_write {
+            return Err(io::Error::other("the list holds changes the journal failed to record"));
+        }
+        tl.save_snapshot_marked(&snapshot_path(&self.path), JournalMark { len: self.len, last_frame: self.last_frame })?;
+        self.since_checkpoint = 0;
+        Ok(())
+    }
+
+    /// Appends a mutating query; searches are not journaled.
+    /// # Errors
+    /// Returns `InvalidInput` if the record is too large for the journal, or an I/O error if it
+    /// cannot be written.
+    pub fn append(&mut self, q: &Query) -> io::Result<()> {
+        if self.prepare(q)? { self.commit()?; }
+        Ok(())
+    }
+
+    /// Encodes `q` as the next record without writing it, so a query the journal cannot take is
+    /// refused before the list is changed; [`Journal::commit`] writes it once the query has applied.
+    /// Returns `false` for queries that are not journaled.
+    /// # Errors
+    /// Returns `InvalidInput` if the record is too large for the journal.
+    pub fn prepare(&mut self, q: &Query) -> io::Result<bool> {
+        self.buf.clear();
+        self.buf.resize(FRAME_LEN, 0);
+        if !encode(q, &mut self.buf) {
+            self.buf.clear();
+            return Ok(false);
+        }
+        if let Err(e) = self.check_writable().and_then(|()| self.check_len()) {
+            self.buf.clear();
+            return Err(e);
+        }
+        Ok(true)
+    }
+
+    /// Writes the record [`Journal::prepare`] encoded; does nothing if there is none.
+    /// # Errors
+    /// Returns an I/O error if the record cannot be written.
+    pub fn commit(&mut self) -> io::Result<()> {
+        if self.buf.is_empty() { return Ok(()); }
+        self.write_frame()
+    }
+
+    /// Records the metadata set on item `idx`
+    /// # Errors
+    /// Returns `InvalidInput` if the record is too large for the journal, or an I/O error if it
+    /// cannot be written.
+    pub fn append_meta(&mut self, idx: Index, meta: &Metadata) -> io::Result<()> {
+        self.check_writable()?;
+        self.buf.clear();
+        self.buf.resize(FRAME_LEN, 0);
+        codec::put_u8(&mut self.buf, RECORD_META);
+        codec::put_u64(&mut self.buf, idx.0);
+        codec::put_u8(&mut self.buf, meta.priority.map_or(0, |p| p as u8));
+        codec::put_u8(&mut self.buf, u8::from(meta.created.is_some()) | u8::from(meta.completed.is_some()) << 1);
+        if let Some(d) = &meta.created { codec::put_str(&mut self.buf, d); }
+        if let Some(d) = &meta.completed { codec::put_str(&mut self.buf, d); }
+        if let Err(e) = self.check_len() {
+            self.buf.clear();
+            return Err(e);
+        }
+        self.write_frame()
+    }
+
+    fn check_writable(&self) -> io::Result<()> {
+        if self.is_read_only() {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, "journal is open read-only"));
+        }
+        Ok(())
+    }
+
+    /// Refuses an encoded record that replay would take for a bad one
+    fn check_len(&self) -> io::Result<()> {
+        let len = self.buf.len() - FRAME_LEN;
+        if len > MAX_RECORD_LEN {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("record of {len} bytes exceeds the {} MiB journal limit", MAX_RECORD_LEN >> 20)));
+        }
+        Ok(())
+    }
+
+    /// Fills in the frame header in front of the encoded record and writes it out.
+    /// A write that fails partway is cut back off, so replay never stops short of later records;
+    /// if even that fails, the journal refuses every further append.
+    fn write_frame(&mut self) -> io::Result<()> {
+        if self.failed {
+            return Err(io::Error::other("journal has a torn record and no longer accepts appends"));
+        }
+        let len = (self.buf.len() - FRAME_LEN) as u32;
+        let sum = codec::checksum(&self.buf[FRAME_LEN..]);
+        self.buf[..4].copy_from_slice(&len.to_le_bytes());
+        self.buf[4..FRAME_LEN].copy_from_slice(&sum.to_le_bytes());
+        if let Err(e) = self.file.write_all(&self.buf) {
+            self.lost_write = true;
+            let good_len = self.len;
+            if self.file.set_len(good_len).and_then(|()| self.file.seek(SeekFrom::Start(good_len))).is_err() {
+                self.failed = true;
+            }
+            return Err(e);
+        }
+        self.len += self.buf.len() as u64;
+        self.last_frame.copy_from_slice(&self.buf[..FRAME_LEN]);
+        self.since_checkpoint += 1;
+        self.buf.clear();
+        Ok(())
+    }
+
+    /// Records all of `tl` as adds, dones and deletes that rebuild it with the same indices on replay
+    /// # Errors
+    /// Returns an I/O error if a record cannot be written.
+    pub fn append_list(&mut self, tl: &TodoList) -> io::Result<()> {
+        for i in 0..tl.next_index().0 {
+            let idx = Index::new(i);
+            match tl.get(idx) {
+                Some(item) => {
+                    self.append(&Query::Add(item.description.clone(), item.tags.clone(), item.due, item.priority))?;
+                    if item.done { self.append(&Query::Done(idx))?; }
+                    if let Some(meta) = &item.meta { self.append_meta(idx, meta)?; }
+                }
+                // A deleted index still has to be used up
+                None => {
+                    self.append(&Query::Add(Description::new(""), Vec::new(), None, None))?;
+                    self.append(&Query::Delete(idx))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Flushes journaled records to stable storage; nothing to do for a read-only journal.
+    /// # Errors
+    /// Returns an I/O error if the OS fails to sync the file.
+    pub fn sync(&mut self) -> io::Result<()> {
+        if self.is_read_only() { return Ok(()); }
+        self.file.sync_data()
+    }
+}
+
+fn not_a_journal() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "not a todo journal")
+}
+
+/// Where `checkpoint` keeps the snapshot of the journal at `path`
+fn snapshot_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".snap");
+    name.into()
+}
+
+/// The file a writer of the journal at `path` holds locked
+fn lock_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".lock");
+    name.into()
+}
+
+/// Whether the journal still ends its first `mark.len` bytes with the record `mark` names
+fn holds(file: &mut File, file_len: u64, mark: JournalMark) -> io::Result<bool> {
+    let header = MAGIC.len() as u64;
+    if mark.len < header || mark.len > file_len { return Ok(false); }
+    if mark.len == header { return Ok(mark.last_frame == [0; FRAME_LEN]); }
+
+    let record_len = (FRAME_LEN as u64) + u64::from(u32::from_le_bytes([mark.last_frame[0], mark.last_frame[1], mark.last_frame[2], mark.last_frame[3]]));
+    let Some(start) = mark.len.checked_sub(record_len).filter(|&start| start >= header) else { return Ok(false) };
+    let mut frame = [0u8; FRAME_LEN];
+    file.seek(SeekFrom::Start(start))?;
+    file.read_exact(&mut frame)?;
+    Ok(frame == mark.last_frame)
+}
+
+/// Applies every intact record after `start` to `tl`; returns where the valid prefix ends
+/// and how many records were applied
+fn replay(file: &mut File, start: JournalMark, file_len: u64, tl: &mut TodoList) -> io::Result<(JournalMark, u64)> {
+    file.seek(SeekFrom::Start(0))?;
+    let mut reader = BufReader::with_capacity(1 << 20, &mut *file);
+    let mut magic = [0u8; 8];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(not_a_journal());
+    }
+    reader.seek(SeekFrom::Start(start.len))?;
+
+    let mut end = start;
+    let mut records = 0;
+    let mut frame = [0u8; FRAME_LEN];
+    let mut payload = Vec::new();
+    loop {
+        if !read_full(&mut reader, &mut frame)? { break; }
+        let len = u32::from_le_bytes([frame[0], frame[1], frame[2], frame[3]]) as usize;
+        let sum = u32::from_le_bytes([frame[4], frame[5], frame[6], frame[7]]);
+        let record = if len > MAX_RECORD_LEN {
+            None
+        } else {
+            payload.resize(len, 0);
+            let complete = read_full(&mut reader, &mut payload)? && codec::checksum(&payload) == sum;
+            if complete { decode(&payload) } else { None }
+        };
+        let Some(record) = record else {
+            // Only the last write can be torn; a bad record with data after it was damaged later
+            if end.len + ((FRAME_LEN + len) as u64) < file_len {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, format!("journal is corrupt at byte {}", end.len)));
+            }
+            break;
+        };
+
+        match record {
+            Record::Query(Query::Add(desc, tags, due, priority)) => {
+                let idx = tl.push(desc, tags);
+                if due.is_some() { tl.set_due(idx, due); }
+                if priority.is_some() { tl.set_priority(idx, priority); }
+            }
+            Record::Query(Query::Done(idx)) => { tl.done_with_index(idx); }
+            Record::Query(Query::Undone(idx)) => { tl.undone_with_index(idx); }
+            Record::Query(Query::Edit(idx, desc, tags)) => { tl.edit(idx, desc, tags); }
+            Record::Query(Query::Delete(idx)) => { tl.remove(idx); }
+            Record::Query(Query::Prio(idx, priority)) => { tl.set_priority(idx, priority); }
+            Record::Query(Query::Search(_) | Query::Count(_)) => {}
+            Record::Meta(idx, meta) => { tl.set_meta(idx, meta); }
+        }
+        end.len += (FRAME_LEN + len) as u64;
+        end.last_frame = frame;
+        records += 1;
+    }
+    Ok((end, records))
+}
+
+/// Like `read_exact`, but reports a short read as `false` instead of an error
+fn read_full<R: Read>(r: &mut R, buf: &mut [u8]) -> io::Result<bool> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match r.read(&mut buf[filled..]) {
+            Ok(0) => return Ok(false),
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(true)
+}
+
+fn encode(q: &Query, buf: &mut Vec<u8>) -> bool {
+    match q {
+        Query::Add(desc, tags, due, priority) => {
+            codec::put_u8(buf, RECORD_ADD);
+            codec::put_str(buf, &desc.0);
+            put_tags(buf, tags);
+            // Optional trailer: due date (0 for none), then priority
+            if due.is_some() || priority.is_some() { codec::put_u32(buf, due.map_or(0, Date::to_packed)); }
+            if priority.is_some() { codec::put_priority(buf, *priority); }
+        }
+        Query::Done(idx) => {
+            codec::put_u8(buf, RECORD_DONE);
+            codec::put_u64(buf, idx.0);
+        }
+        Query::Undone(idx) => {
+            codec::put_u8(buf, RECORD_UNDONE);
+            codec::put_u64(buf, idx.0);
+        }
+        Query::Edit(idx, desc, tags) => {
+            codec::put_u8(buf, RECORD_EDIT);
+            codec::put_u64(buf, idx.0);
+            codec::put_u8(buf, u8::from(desc.is_some()) | u8::from(tags.is_some()) << 1);
+            if let Some(desc) = desc { codec::put_str(buf, &desc.0); }
+            if let Some(tags) = tags { put_tags(buf, tags); }
+        }
+        Query::Delete(idx) => {
+            codec::put_u8(buf, RECORD_DELETE);
+            codec::put_u64(buf, idx.0);
+        }
+        Query::Prio(idx, priority) => {
+            codec::put_u8(buf, RECORD_PRIO);
+            codec::put_u64(buf, idx.0);
+            codec::put_priority(buf, *priority);
+        }
+        Query::Search(_) | Query::Count(_) => return false,
+    }
+    true
+}
+
+fn put_tags(buf: &mut Vec<u8>, tags: &[Tag]) {
+    codec::put_u32(buf, tags.len() as u32);
+    for tag in tags { codec::put_str(buf, &tag.0); }
+}
+
+fn read_tags(r: &mut Reader) -> Option<Vec<Tag>> {
+    let count = r.u32()? as usize;
+    let mut tags = Vec::with_capacity(count.min(64));
+    for _ in 0..count { tags.push(Tag::new(r.str()?)); }
+    Some(tags)
+}
+
+fn decode(payload: &[u8]) -> Option<Record> {
+    let mut r = Reader::new(payload);
+    let q = match r.u8()? {
+        RECORD_ADD => {
+            let desc = Description::new(r.str()?);
+            let tags = read_tags(&mut r)?;
+            // Older records end after the tags, or after the due date
+            let due = match if r.is_empty() { 0 } else { r.u32()? } {
+                0 => None,
+                packed => Some(Date::from_packed(packed)?),
+            };
+            let priority = if r.is_empty() { None } else { r.priority()? };
+            Query::Add(desc, tags, due, priority)
+        }
+        RECORD_DONE => Query::Done(Index::new(r.u64()?)),
+        RECORD_UNDONE => Query::Undone(Index::new(r.u64()?)),
+        RECORD_DELETE => Query::Delete(Index::new(r.u64()?)),
+        RECORD_PRIO => Query::Prio(Index::new(r.u64()?), r.priority()?),
+        RECORD_EDIT => {
+            let idx = Index::new(r.u64()?);
+            let present = r.u8()?;
+            let desc = if present & 1 != 0 { Some(Description::new(r.str()?)) } else { None };
+            let tags = if present & 2 != 0 { Some(read_tags(&mut r)?) } else { None };
+            Query::Edit(idx, desc, tags)
+        }
+        RECORD_META => {
+            let idx = Index::new(r.u64()?);
+            let priority = match r.u8()? {
+                0 => None,
+                p @ b'A'..=b'Z' => Some(char::from(p)),
+                _ => return None,
+            };
+            let present = r.u8()?;
+            let created = if present & 1 != 0 { Some(r.str()?.to_owned()) } else { None };
+            let completed = if present & 2 != 0 { Some(r.str()?.to_owned()) } else { None };
+            return r.is_empty().then_some(Record::Meta(idx, Metadata { priority, created, completed }));
+        }
+        _ => return None,
+    };
+    r.is_empty().then_some(Record::Query(q))
+}
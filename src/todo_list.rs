@@ -1,43 +1,21 @@
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
 use std::fmt::{self, Display};
-use rayon::prelude::*;
-
-use crate::SearchParams;
-
-/// Check if `sub` is a subsequence of `text` (characters appear in order but not necessarily contiguous)
-#[inline(always)]
-fn is_subsequence(sub: &[u8], text: &[u8]) -> bool {
-    if sub.is_empty() { return true; }
-    if sub.len() > text.len() { return false; }
-    let mut si = 0;
-    for &ch in text {
-        if ch == sub[si] {
-            si += 1;
-            if si == sub.len() { return true; }
-        }
-    }
-    false
-}
+use std::ops::Bound;
+use std::str::FromStr;
+use unicode_general_category::{get_general_category, GeneralCategory};
 
-/// Bitmask of which letters (a-z) are present in the string
-#[inline(always)]
-fn char_mask(s: &[u8]) -> u32 {
-    let mut mask = 0u32;
-    for &c in s {
-        if c.is_ascii_lowercase() {
-            mask |= 1 << (c - b'a');
-        } else if c.is_ascii_uppercase() {
-            mask |= 1 << (c - b'A');
-        }
-    }
-    mask
-}
+use crate::{Clock, Date, DateOp, SearchExpr, SearchParams, SortDirection, SortKey, StatusFilter, SystemClock};
 
-/// Convert string to lowercase bytes
-#[inline(always)]
-fn to_lower_bytes(s: &str) -> Vec<u8> {
-    s.as_bytes().iter().map(|&c| c.to_ascii_lowercase()).collect()
-}
+mod csv;
+mod score;
+mod snapshot;
+mod term_index;
+mod todo_txt;
+pub use self::csv::{CsvError, CsvRowError};
+pub use snapshot::{SnapshotError, SNAPSHOT_VERSION};
+pub(crate) use snapshot::JournalMark;
+use term_index::TermIndex;
 
 /// Apply function with lowercase version of string (uses thread-local buffer)
 #[inline]
@@ -57,6 +35,32 @@ where F: FnOnce(&str) -> R {
     })
 }
 
+/// How a search term is compared against indexed words and tags.
+/// `Exact` only looks terms up, so its searches never build the subsequence
+/// postings that the other modes need, which saves their memory on very large lists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MatchMode {
+    /// Term characters appear in order, not necessarily contiguous
+    #[default]
+    Subsequence,
+    /// Word starts with the term
+    Prefix,
+    /// Word equals the term
+    Exact,
+}
+
+impl FromStr for MatchMode {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "subsequence" => Ok(Self::Subsequence),
+            "prefix" => Ok(Self::Prefix),
+            "exact" => Ok(Self::Exact),
+            _ => Err(format!("unknown match mode `{s}` (expected subsequence, prefix or exact)")),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub struct Index(pub u64);
 
@@ -68,11 +72,44 @@ impl Display for Index {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { write!(f, "{}", self.0) }
 }
 
+/// Characters that make up a word: Unicode letters, digits and combining marks (so decomposed
+/// `café` stays one word), `-`, and the zero-width non-joiner used inside Persian words
+#[must_use] pub fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '-' || c == '\u{200c}' || (!c.is_ascii() && is_mark(c))
+}
+
+fn is_mark(c: char) -> bool {
+    matches!(get_general_category(c), GeneralCategory::NonspacingMark | GeneralCategory::SpacingMark | GeneralCategory::EnclosingMark)
+}
+
+/// A tag name: word characters, optionally after an `@` marking a todo.txt context
+#[must_use] pub fn is_tag_name(s: &str) -> bool {
+    let name = s.strip_prefix('@').unwrap_or(s);
+    !name.is_empty() && name.chars().all(is_word_char)
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Description(pub String);
 
 impl Description {
     #[must_use] pub fn new(s: &str) -> Self { Self(s.to_owned()) }
+    
+    /// The words indexed for search: runs of word characters with at least one letter or digit,
+    /// so `ship v2.1 by 5pm!` yields `ship`, `v2`, `1`, `by` and `5pm`
+    pub fn words(&self) -> impl Iterator<Item = &str> {
+        self.0.split(|c| !is_word_char(c)).filter(|w| w.chars().any(char::is_alphanumeric))
+    }
+    
+    /// The description as written between quotes, with `"` and `\` backslash-escaped
+    #[must_use] pub fn escaped(&self) -> Cow<'_, str> {
+        if !self.0.contains(['"', '\\']) { return Cow::Borrowed(&self.0); }
+        let mut s = String::with_capacity(self.0.len() + 2);
+        for c in self.0.chars() {
+            if c == '"' || c == '\\' { s.push('\\'); }
+            s.push(c);
+        }
+        Cow::Owned(s)
+    }
 }
 
 impl Display for Description {
@@ -90,94 +127,189 @@ impl Display for Tag {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { write!(f, "{}", self.0) }
 }
 
+/// How urgent an item is; ordered from `Low` to `High`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    Low,
+    Medium,
+    High,
+}
+
+impl Priority {
+    pub const ALL: [Self; 3] = [Self::Low, Self::Medium, Self::High];
+
+    #[must_use] pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Low => "low",
+            Self::Medium => "medium",
+            Self::High => "high",
+        }
+    }
+}
+
+impl FromStr for Priority {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "low" => Ok(Self::Low),
+            "medium" => Ok(Self::Medium),
+            "high" => Ok(Self::High),
+            _ => Err(format!("unknown priority `{s}` (expected low, medium or high)")),
+        }
+    }
+}
+
+impl Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { f.write_str(self.as_str()) }
+}
+
+/// todo.txt fields the list has no use for, kept so imported items export unchanged
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Metadata {
+    /// todo.txt priority letter, when the item's `Priority` doesn't already imply it (`D` to `Z`)
+    pub priority: Option<char>,
+    /// `YYYY-MM-DD`
+    pub created: Option<String>,
+    /// `YYYY-MM-DD`
+    pub completed: Option<String>,
+}
+
+impl Metadata {
+    #[must_use] pub fn is_empty(&self) -> bool { *self == Self::default() }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct TodoItem {
     pub index: Index,
     pub description: Description,
     pub tags: Vec<Tag>,
     pub done: bool,
+    pub due: Option<Date>,
+    pub priority: Option<Priority>,
+    // Boxed since most items never carry any
+    pub meta: Option<Box<Metadata>>,
 }
 
 impl TodoItem {
     #[must_use] pub fn new(index: Index, description: Description, tags: Vec<Tag>) -> Self {
-        Self { index, description, tags, done: false }
+        Self { index, description, tags, done: false, due: None, priority: None, meta: None }
     }
 }
 
 impl Display for TodoItem {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{} \"{}\"", self.index, self.description)?;
+        write!(f, "{} \"{}\"", self.index, self.description.escaped())?;
         for tag in &self.tags { write!(f, " #{}", tag.0)?; }
+        if let Some(due) = self.due { write!(f, " due:{due}")?; }
+        if let Some(priority) = self.priority { write!(f, " !{priority}")?; }
         Ok(())
     }
 }
 
-struct WordInfo {
-    lower: Box<[u8]>,
-    mask: u32,
-    len: u8,
-    items: Vec<u32>,
+/// One page of search results
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchPage<T> {
+    pub items: Vec<T>,
+    /// Matching items across all pages
+    pub total: usize,
 }
 
+impl<T> SearchPage<T> {
+    fn map<U>(self, f: impl FnMut(T) -> U) -> SearchPage<U> {
+        SearchPage { items: self.items.into_iter().map(f).collect(), total: self.total }
+    }
+}
+
+/// The part of the matching items a search returns: `take` of them, after skipping `skip`
+#[derive(Debug, Clone, Copy)]
+struct Window {
+    skip: usize,
+    take: usize,
+}
+
+#[derive(Default)]
 pub struct TodoList {
     top_index: u64,
-    items: Vec<TodoItem>,
+    // Deleted items are tombstoned as `None` so indices stay positional
+    items: Vec<Option<TodoItem>>,
     done_flags: Vec<bool>,
+    // Live items, and how many of them are done
+    live_count: usize,
+    done_count: usize,
+    // Terms whose posting list has emptied out since the last compaction
+    empty_terms: usize,
     
-    words: Vec<WordInfo>,
-    word_map: HashMap<Box<str>, u32>,
-    // Index: char -> list of word indices containing that char
-    char_index: [Vec<u32>; 26],
+    words: TermIndex,
+    tags: TermIndex,
+    // Sorted item ids per due date
+    due: BTreeMap<Date, Vec<u32>>,
+    // Sorted item ids per priority, indexed by `Priority as usize`
+    priorities: [Vec<u32>; 3],
+    // What `due:today` and `overdue` compare against; the system clock when unset
+    clock: Option<Box<dyn Clock>>,
     
-    tags_vec: Vec<WordInfo>,
-    tag_map: HashMap<Box<str>, u32>,
-    tag_char_index: [Vec<u32>; 26],
+    // How search terms are matched against indexed words and tags
+    match_mode: MatchMode,
     
-    // Fast mode: skip subsequence search for performance
-    fast_mode: bool,
+    // Maximum number of items a search returns (`None` = unlimited)
+    limit: Option<usize>,
     
     // Concise mode: output only indices without descriptions/tags
     concise_mode: bool,
-}
-
-impl Default for TodoList {
-    fn default() -> Self {
-        Self {
-            top_index: 0,
-            items: Vec::new(),
-            done_flags: Vec::new(),
-            words: Vec::new(),
-            word_map: HashMap::new(),
-            char_index: Default::default(),
-            tags_vec: Vec::new(),
-            tag_map: HashMap::new(),
-            tag_char_index: Default::default(),
-            fast_mode: false,
-            concise_mode: false,
-        }
-    }
+    
+    // Include each found item's relevance score in JSON output
+    show_scores: bool,
 }
 
 impl TodoList {
     #[must_use] pub fn new() -> Self { Self::default() }
     
-    #[must_use] pub fn with_mode(fast_mode: bool) -> Self {
-        Self { fast_mode, ..Self::default() }
+    #[must_use] pub fn with_match_mode(self, match_mode: MatchMode) -> Self {
+        Self { match_mode, ..self }
+    }
+    
+    #[must_use] pub fn with_limit(self, limit: Option<usize>) -> Self {
+        Self { limit, ..self }
     }
     
-    #[must_use] pub fn with_modes(fast_mode: bool, concise_mode: bool) -> Self {
-        Self { fast_mode, concise_mode, ..Self::default() }
+    #[must_use] pub fn with_concise(self, concise_mode: bool) -> Self {
+        Self { concise_mode, ..self }
+    }
+    
+    #[must_use] pub fn with_scores(self, show_scores: bool) -> Self {
+        Self { show_scores, ..self }
+    }
+    
+    #[must_use] pub fn with_clock(self, clock: impl Clock + 'static) -> Self {
+        Self { clock: Some(Box::new(clock)), ..self }
+    }
+    
+    /// Today's date according to the list's clock
+    #[must_use] pub fn today(&self) -> Date {
+        self.clock.as_ref().map_or_else(|| SystemClock.today(), |c| c.today())
+    }
+    
+    #[must_use] pub fn match_mode(&self) -> MatchMode {
+        self.match_mode
+    }
+    
+    #[must_use] pub fn limit(&self) -> Option<usize> {
+        self.limit
     }
     
     #[must_use] pub fn is_concise(&self) -> bool {
         self.concise_mode
     }
+    
+    #[must_use] pub fn shows_scores(&self) -> bool {
+        self.show_scores
+    }
 
     pub fn push(&mut self, description: Description, tags: Vec<Tag>) -> Index {
         let idx = self.top_index;
         let item_idx = self.items.len() as u32;
         
-        for word in description.0.split_whitespace() {
+        for word in description.words() {
             with_lower(word, |lower| self.add_word(lower, item_idx));
         }
         
@@ -186,8 +318,9 @@ impl TodoList {
         }
         
         self.done_flags.push(false);
+        self.live_count += 1;
         let item = TodoItem::new(Index(idx), description, tags);
-        self.items.push(item);
+        self.items.push(Some(item));
         self.top_index = idx + 1;
         Index(idx)
     }
@@ -200,257 +333,495 @@ impl TodoList {
         self.add_indexed_term(lower, item_idx, true);
     }
     
+    fn term_index(&mut self, is_tag: bool) -> &mut TermIndex {
+        if is_tag { &mut self.tags } else { &mut self.words }
+    }
+    
     /// Add a term (word or tag) to the appropriate index
     fn add_indexed_term(&mut self, lower: &str, item_idx: u32, is_tag: bool) {
-        let (terms, term_map, char_idx) = if is_tag {
-            (&mut self.tags_vec, &mut self.tag_map, &mut self.tag_char_index)
-        } else {
-            (&mut self.words, &mut self.word_map, &mut self.char_index)
-        };
-        
-        if let Some(&idx) = term_map.get(lower) {
-            terms[idx as usize].items.push(item_idx);
-        } else {
-            let term_idx = terms.len() as u32;
-            let bytes: Box<[u8]> = lower.as_bytes().into();
-            let mask = char_mask(&bytes);
-            let len = bytes.len().min(255) as u8;
-            
-            // Add to char index for each unique letter
-            let mut added = 0u32;
-            for &c in bytes.iter() {
-                if c.is_ascii_lowercase() {
-                    let ci = (c - b'a') as usize;
-                    let bit = 1u32 << ci;
-                    if (added & bit) == 0 {
-                        added |= bit;
-                        char_idx[ci].push(term_idx);
-                    }
+        let (term_idx, is_new) = self.intern_term(lower, is_tag);
+        // Postings stay sorted and duplicate-free; appends from `push` land at the end
+        let items = self.term_index(is_tag).items_mut(term_idx);
+        if let Err(pos) = items.binary_search(&item_idx) {
+            let refilled = items.is_empty() && !is_new;
+            items.insert(pos, item_idx);
+            if refilled {
+                self.empty_terms -= 1;
+            }
+        }
+    }
+    
+    /// Remove an item from a term's posting list, if the term is indexed
+    fn remove_indexed_term(&mut self, lower: &str, item_idx: u32, is_tag: bool) {
+        let terms = self.term_index(is_tag);
+        if let Some(term_idx) = terms.get(lower) {
+            let items = terms.items_mut(term_idx);
+            if let Ok(pos) = items.binary_search(&item_idx) {
+                items.remove(pos);
+                if items.is_empty() {
+                    self.empty_terms += 1;
                 }
             }
-            
-            terms.push(WordInfo { lower: bytes, mask, len, items: vec![item_idx] });
-            term_map.insert(lower.into(), term_idx);
         }
     }
+    
+    /// Look up a term, registering it in the term table and search indexes if it is new.
+    /// Returns the term index and whether the term was just created.
+    fn intern_term(&mut self, lower: &str, is_tag: bool) -> (u32, bool) {
+        self.term_index(is_tag).intern(lower)
+    }
+
+    /// Index the next added item will get
+    #[must_use] pub fn next_index(&self) -> Index {
+        Index(self.top_index)
+    }
+    
+    /// Live items in index order
+    pub fn items(&self) -> impl Iterator<Item = &TodoItem> {
+        self.items.iter().flatten()
+    }
+
+    #[must_use] pub fn get(&self, idx: Index) -> Option<&TodoItem> {
+        self.items.get(idx.0 as usize)?.as_ref()
+    }
 
     pub fn done_with_index(&mut self, idx: Index) -> Option<Index> {
         let i = idx.0 as usize;
-        if i < self.done_flags.len() && !self.done_flags[i] {
+        let item = self.items.get_mut(i)?.as_mut()?;
+        if !self.done_flags[i] {
             self.done_flags[i] = true;
-            self.items[i].done = true;
+            self.done_count += 1;
+            item.done = true;
             return Some(idx);
         }
         None
     }
 
-    #[must_use] 
-    pub fn search(&self, sp: &SearchParams) -> Vec<&TodoItem> {
-        if sp.words.is_empty() && sp.tags.is_empty() {
-            // Natural order (ascending by index)
-            return self.items.iter()
-                .enumerate()
-                .filter(|(i, _)| !self.done_flags[*i])
-                .map(|(_, item)| item)
-                .collect();
+    /// Sets or clears an item's due date
+    pub fn set_due(&mut self, idx: Index, due: Option<Date>) -> Option<Index> {
+        let i = idx.0 as usize;
+        let item = self.items.get_mut(i)?.as_mut()?;
+        let old = std::mem::replace(&mut item.due, due);
+        if let Some(old) = old { unindex_due(&mut self.due, old, i as u32); }
+        if let Some(due) = due {
+            let ids = self.due.entry(due).or_default();
+            // Usually set right after `push`, so the id goes at the end
+            let pos = ids.partition_point(|&x| x < i as u32);
+            ids.insert(pos, i as u32);
         }
+        Some(idx)
+    }
+
+    /// Sets or clears an item's priority
+    pub fn set_priority(&mut self, idx: Index, priority: Option<Priority>) -> Option<Index> {
+        let i = idx.0 as usize;
+        let item = self.items.get_mut(i)?.as_mut()?;
+        let old = std::mem::replace(&mut item.priority, priority);
+        if let Some(old) = old { remove_sorted(&mut self.priorities[old as usize], i as u32); }
+        if let Some(priority) = priority {
+            let ids = &mut self.priorities[priority as usize];
+            let pos = ids.partition_point(|&x| x < i as u32);
+            ids.insert(pos, i as u32);
+        }
+        Some(idx)
+    }
+
+    /// Replaces an item's metadata; an empty `Metadata` clears it
+    pub fn set_meta(&mut self, idx: Index, meta: Metadata) -> Option<Index> {
+        let item = self.items.get_mut(idx.0 as usize)?.as_mut()?;
+        item.meta = (!meta.is_empty()).then(|| Box::new(meta));
+        Some(idx)
+    }
+
+    /// Reopens a completed item so it shows up in searches again
+    pub fn undone_with_index(&mut self, idx: Index) -> Option<Index> {
+        let i = idx.0 as usize;
+        let item = self.items.get_mut(i)?.as_mut()?;
+        if self.done_flags[i] {
+            self.done_flags[i] = false;
+            self.done_count -= 1;
+            item.done = false;
+            return Some(idx);
+        }
+        None
+    }
+
+    /// Replaces an item's description and/or tags, moving it between posting lists
+    pub fn edit(&mut self, idx: Index, description: Option<Description>, tags: Option<Vec<Tag>>) -> Option<Index> {
+        let i = idx.0 as usize;
+        // Detach the item while its postings are rewritten
+        let mut item = self.items.get_mut(i)?.take()?;
+        let item_idx = i as u32;
         
-        // Use sorted Vec instead of HashSet for better cache locality
-        let mut candidates: Option<Vec<u32>> = None;
-        
-        // Find smallest matching set first
-        let mut smallest_idx: Option<usize> = None;
-        let mut smallest_size = usize::MAX;
-        
-        // Collect all matching word indices with their sizes
-        let mut word_matches: Vec<(usize, &Vec<u32>)> = Vec::with_capacity(sp.words.len());
+        if let Some(description) = description {
+            self.unindex_description(&item.description, item_idx);
+            for word in description.words() {
+                with_lower(word, |lower| self.add_word(lower, item_idx));
+            }
+            item.description = description;
+        }
         
-        for (i, sw) in sp.words.iter().enumerate() {
-            let search_bytes = to_lower_bytes(&sw.0);
-            
-            // In fast mode, only do exact word matches for performance
-            if self.fast_mode {
-                if let Some(&word_idx) = self.word_map.get(unsafe { std::str::from_utf8_unchecked(&search_bytes) }) {
-                    let items = &self.words[word_idx as usize].items;
-                    word_matches.push((i, items));
-                    if items.len() < smallest_size {
-                        smallest_size = items.len();
-                        smallest_idx = Some(word_matches.len() - 1);
-                    }
-                    continue;
+        if let Some(tags) = tags {
+            self.unindex_tags(&item.tags, item_idx);
+            for tag in &tags {
+                with_lower(&tag.0, |lower| self.add_tag(lower, item_idx));
+            }
+            item.tags = tags;
+        }
+        self.items[i] = Some(item);
+        self.maybe_compact();
+        Some(idx)
+    }
+
+    /// Deletes an item for good: it is dropped from every posting list and its index is never reused
+    pub fn remove(&mut self, idx: Index) -> Option<TodoItem> {
+        let i = idx.0 as usize;
+        let item = self.items.get_mut(i)?.take()?;
+        self.unindex_description(&item.description, i as u32);
+        self.unindex_tags(&item.tags, i as u32);
+        if let Some(due) = item.due { unindex_due(&mut self.due, due, i as u32); }
+        if let Some(priority) = item.priority { remove_sorted(&mut self.priorities[priority as usize], i as u32); }
+        self.live_count -= 1;
+        if std::mem::take(&mut self.done_flags[i]) { self.done_count -= 1; }
+        self.maybe_compact();
+        Some(item)
+    }
+
+    fn unindex_description(&mut self, description: &Description, item_idx: u32) {
+        for word in description.words() {
+            with_lower(word, |lower| self.remove_indexed_term(lower, item_idx, false));
+        }
+    }
+
+    fn unindex_tags(&mut self, tags: &[Tag], item_idx: u32) {
+        for tag in tags {
+            with_lower(&tag.0, |lower| self.remove_indexed_term(lower, item_idx, true));
+        }
+    }
+
+    /// Compact once a sizeable share of the term tables has no postings left
+    fn maybe_compact(&mut self) {
+        const MIN_EMPTY_TERMS: usize = 1024;
+        if self.empty_terms >= MIN_EMPTY_TERMS && self.empty_terms * 4 >= self.words.len() + self.tags.len() {
+            self.compact();
+        }
+    }
+
+    /// Drops words and tags whose posting lists are empty, renumbering the term tables and search indexes
+    pub fn compact(&mut self) {
+        self.words.compact();
+        self.tags.compact();
+        self.empty_terms = 0;
+    }
+
+    /// The page of matching items `sp` asks for
+    #[must_use] 
+    pub fn search(&self, sp: &SearchParams) -> Vec<&TodoItem> {
+        self.search_page(sp).items
+    }
+    
+    /// The page of matching items `sp` asks for, along with how many items match in all
+    #[must_use]
+    pub fn search_page(&self, sp: &SearchParams) -> SearchPage<&TodoItem> {
+        let mut window = self.window(sp);
+        let matched = self.eval(&sp.expr);
+        let matched = matched.as_deref();
+        let desc = sp.sort.direction == SortDirection::Desc;
+        let mut items = Vec::new();
+        match sp.sort.key {
+            SortKey::Index => self.collect(self.ordered_ids(matched, desc), sp.status, &mut window, &mut items, |_| true),
+            SortKey::Priority => {
+                // Walk the priority buckets, so a full page stops early
+                let mut levels = Priority::ALL;
+                if desc { levels.reverse(); }
+                for p in levels {
+                    let bucket = &self.priorities[p as usize];
+                    let ids = match matched {
+                        Some(m) => Cow::Owned(intersect_sorted(m, bucket)),
+                        None => Cow::Borrowed(bucket.as_slice()),
+                    };
+                    self.collect(ids.iter().copied(), sp.status, &mut window, &mut items, |_| true);
                 }
-                return Vec::new();
+                self.collect(self.ordered_ids(matched, false), sp.status, &mut window, &mut items, |item| item.priority.is_none());
             }
-            
-            if search_bytes.is_empty() { continue; }
-            
-            let search_mask = char_mask(&search_bytes);
-            let search_len = search_bytes.len() as u8;
-            
-            // Find rarest character in search term
-            let mut best_ci = 0;
-            let mut best_count = usize::MAX;
-            for &c in &search_bytes {
-                if c >= b'a' && c <= b'z' {
-                    let ci = (c - b'a') as usize;
-                    if self.char_index[ci].len() < best_count {
-                        best_count = self.char_index[ci].len();
-                        best_ci = ci;
-                    }
+            SortKey::Due => {
+                // The due map is already in date order
+                let dates: Box<dyn Iterator<Item = &Vec<u32>>> = if desc { Box::new(self.due.values().rev()) } else { Box::new(self.due.values()) };
+                for ids in dates {
+                    if items.len() >= window.take { break; }
+                    let ids = ids.iter().copied().filter(|i| matched.is_none_or(|m| m.binary_search(i).is_ok()));
+                    self.collect(ids, sp.status, &mut window, &mut items, |_| true);
                 }
+                self.collect(self.ordered_ids(matched, false), sp.status, &mut window, &mut items, |item| item.due.is_none());
             }
-            
-            // Parallel: filter matching words and flat_map their items
-            let mut matching: Vec<u32> = self.char_index[best_ci]
-                .par_iter()
-                .filter_map(|&word_idx| {
-                    let w = &self.words[word_idx as usize];
-                    // Check word length first for performance
-                    if w.len >= search_len {
-                        // Then check character mask for quick rejection
-                        if (search_mask & w.mask) == search_mask {
-                            // Finally, verify exact subsequence match
-                            if is_subsequence(&search_bytes, &w.lower) {
-                                Some(&w.items[..])
-                            } else {
-                                None
-                            }
-                        } else {
-                            None
-                        }
-                    } else {
-                        None
-                    }
-                })
-                .flatten()
-                .copied()
-                .collect();
-            matching.sort_unstable();
-            matching.dedup();
-            
-            // Intersect with existing candidates or set as initial
-            candidates = Some(match candidates.take() {
-                None => matching,
-                Some(c) => intersect_sorted(&c, &matching),
-            });
-            
-            if candidates.as_ref().map_or(false, |c| c.is_empty()) { 
-                return Vec::new(); 
+            SortKey::Description => {
+                let keyed = self.matching_items(matched, sp.status).map(|item| (item.description.0.to_lowercase(), item)).collect();
+                return ranked(keyed, desc, window).map(|(_, item)| item);
+            }
+            SortKey::Relevance => {
+                let terms = self.scored_terms(&sp.expr);
+                return self.ranked_by_relevance(matched, sp, &terms, window).map(|(_, item)| item);
             }
         }
-        
-        // Now process exact matches starting with smallest
-        if !word_matches.is_empty() {
-            if let Some(si) = smallest_idx {
-                // Start with smallest set
-                let (_, items) = word_matches[si];
-                let mut result: Vec<u32> = match candidates.take() {
-                    None => items.to_vec(),
-                    Some(c) => intersect_sorted(&c, items),
-                };
-                
-                // Intersect with other sets
-                for (j, (_, items)) in word_matches.iter().enumerate() {
-                    if j != si {
-                        result = intersect_sorted(&result, items);
-                        if result.is_empty() { return Vec::new(); }
+        SearchPage { items, total: self.count_matching(matched, sp.status) }
+    }
+    
+    /// How many items match `sp`'s expression and status, counted from the posting lists
+    /// without collecting the items; sorting, paging and `--limit` don't apply
+    #[must_use]
+    pub fn count(&self, sp: &SearchParams) -> usize {
+        self.count_matching(self.eval(&sp.expr).as_deref(), sp.status)
+    }
+    
+    /// Like `search_page`, with each item's relevance to the search's words and tags
+    #[must_use]
+    pub fn search_scored(&self, sp: &SearchParams) -> SearchPage<(&TodoItem, u32)> {
+        let terms = self.scored_terms(&sp.expr);
+        if sp.sort.key == SortKey::Relevance {
+            // Ranking already scored every match
+            let matched = self.eval(&sp.expr);
+            return self.ranked_by_relevance(matched.as_deref(), sp, &terms, self.window(sp)).map(|(score, item)| (item, score));
+        }
+        self.search_page(sp).map(|item| (item, self.score(item, &terms)))
+    }
+    
+    /// The part of the matches `sp` asks for
+    fn window(&self, sp: &SearchParams) -> Window {
+        // `--limit` caps every page, whatever the query asks for
+        let take = sp.limit.unwrap_or(usize::MAX).min(self.limit.unwrap_or(usize::MAX));
+        Window { skip: sp.offset, take }
+    }
+    
+    /// The page of `matched` that `window` selects, ranked by relevance to `terms`, each item with its score
+    fn ranked_by_relevance<'a>(&'a self, matched: Option<&[u32]>, sp: &SearchParams, terms: &[(String, bool)], window: Window) -> SearchPage<(u32, &'a TodoItem)> {
+        let keyed = self.matching_items(matched, sp.status).map(|item| (self.score(item, terms), item)).collect();
+        ranked(keyed, sp.sort.direction == SortDirection::Desc, window)
+    }
+    
+    /// How well `item` matches the words and tags `expr` looks for; 0 if it matches none of them
+    #[must_use] pub fn relevance(&self, item: &TodoItem, expr: &SearchExpr) -> u32 {
+        self.score(item, &self.scored_terms(expr))
+    }
+    
+    /// The lowercased words and tags of `expr` that count towards relevance
+    fn scored_terms(&self, expr: &SearchExpr) -> Vec<(String, bool)> {
+        let mut terms = Vec::new();
+        positive_terms(expr, &mut terms);
+        terms.into_iter().map(|(term, is_tag)| (with_lower(term, str::to_owned), is_tag)).collect()
+    }
+    
+    /// Sum over `terms` of the best score among the item's words (or tags) that the term matches
+    fn score(&self, item: &TodoItem, terms: &[(String, bool)]) -> u32 {
+        let mut total = 0;
+        for (term, is_tag) in terms {
+            let best = if *is_tag {
+                item.tags.iter().filter_map(|t| with_lower(&t.0, |t| self.term_score(term, t))).max()
+            } else {
+                item.description.words().filter_map(|w| with_lower(w, |w| self.term_score(term, w))).max()
+            };
+            total += best.map_or(0, |s| score::weighted(s, *is_tag));
+        }
+        total
+    }
+    
+    /// `term`'s score against `word`, if it matches under the current match mode
+    fn term_score(&self, term: &str, word: &str) -> Option<u32> {
+        let matches = match self.match_mode {
+            MatchMode::Subsequence => true,
+            MatchMode::Prefix => word.starts_with(term),
+            MatchMode::Exact => word == term,
+        };
+        if matches { score::term_score(term, word) } else { None }
+    }
+    
+    /// The ids in `matched`, or every slot if `None`, ascending or descending
+    fn ordered_ids<'b>(&self, matched: Option<&'b [u32]>, desc: bool) -> Box<dyn Iterator<Item = u32> + 'b> {
+        let all = 0..self.items.len() as u32;
+        match (matched, desc) {
+            (Some(m), false) => Box::new(m.iter().copied()),
+            (Some(m), true) => Box::new(m.iter().rev().copied()),
+            (None, false) => Box::new(all),
+            (None, true) => Box::new(all.rev()),
+        }
+    }
+    
+    /// Appends the items in `ids` that are live and pass `status` and `keep` to `result`,
+    /// once `window` has skipped its share and until it holds `window.take`
+    fn collect<'a>(
+        &'a self,
+        ids: impl Iterator<Item = u32>,
+        status: StatusFilter,
+        window: &mut Window,
+        result: &mut Vec<&'a TodoItem>,
+        keep: impl Fn(&TodoItem) -> bool,
+    ) {
+        for i in ids {
+            if result.len() >= window.take { break; }
+            if status.matches(self.done_flags[i as usize]) {
+                if let Some(item) = &self.items[i as usize] {
+                    if !keep(item) { continue; }
+                    if window.skip > 0 {
+                        window.skip -= 1;
+                    } else {
+                        result.push(item);
                     }
                 }
-                candidates = Some(result);
             }
         }
-        
-        for st in &sp.tags {
-            let search_bytes = to_lower_bytes(&st.0);
-            
-            // In fast mode, only do exact tag matches for performance
-            if self.fast_mode {
-                if let Some(&tag_idx) = self.tag_map.get(unsafe { std::str::from_utf8_unchecked(&search_bytes) }) {
-                    let matching = &self.tags_vec[tag_idx as usize].items;
-                    candidates = Some(match candidates {
-                        None => matching.to_vec(),
-                        Some(c) => intersect_sorted(&c, matching),
-                    });
-                    if candidates.as_ref().map_or(true, |c| c.is_empty()) { 
-                        return Vec::new(); 
+    }
+    
+    /// The live items in `matched` (every item if `None`) that pass `status`, in index order
+    fn matching_items<'a: 'b, 'b>(&'a self, matched: Option<&'b [u32]>, status: StatusFilter) -> impl Iterator<Item = &'a TodoItem> + 'b {
+        self.ordered_ids(matched, false)
+            .filter(move |&i| status.matches(self.done_flags[i as usize]))
+            .filter_map(move |i| self.items[i as usize].as_ref())
+    }
+    
+    /// How many live items in `matched` (every item if `None`) pass `status`
+    fn count_matching(&self, matched: Option<&[u32]>, status: StatusFilter) -> usize {
+        match (matched, status) {
+            // Posting lists never reference deleted items
+            (Some(ids), StatusFilter::All) => ids.len(),
+            (Some(ids), _) => ids.iter().filter(|&&i| status.matches(self.done_flags[i as usize])).count(),
+            (None, StatusFilter::Open) => self.live_count - self.done_count,
+            (None, StatusFilter::Done) => self.done_count,
+            (None, StatusFilter::All) => self.live_count,
+        }
+    }
+    
+    /// Sorted ids of the items matching `expr`, or `None` if it doesn't constrain the result at all
+    fn eval(&self, expr: &SearchExpr) -> Option<Cow<'_, [u32]>> {
+        match expr {
+            SearchExpr::Word(w) if w.0.is_empty() => None,
+            SearchExpr::Tag(t) if t.0.is_empty() => None,
+            SearchExpr::Word(w) => Some(self.match_term(&w.0, false)),
+            SearchExpr::Tag(t) => Some(self.match_term(&t.0, true)),
+            SearchExpr::And(children) => {
+                let mut sets = Vec::with_capacity(children.len());
+                for child in children.iter().filter(|c| !matches!(c, SearchExpr::Not(_))) {
+                    match self.eval(child) {
+                        Some(s) if s.is_empty() => return Some(Cow::Borrowed(&[])),
+                        Some(s) => sets.push(s),
+                        None => {}
                     }
-                    continue;
                 }
-                return Vec::new();
-            }
-            
-            if search_bytes.is_empty() { continue; }
-            
-            let search_mask = char_mask(&search_bytes);
-            
-            let mut best_ci = 0;
-            let mut best_count = usize::MAX;
-            for &c in &search_bytes {
-                if c >= b'a' && c <= b'z' {
-                    let ci = (c - b'a') as usize;
-                    if self.tag_char_index[ci].len() < best_count {
-                        best_count = self.tag_char_index[ci].len();
-                        best_ci = ci;
-                    }
+                // Intersect starting from the smallest set
+                sets.sort_unstable_by_key(|s| s.len());
+                let mut result: Option<Cow<[u32]>> = None;
+                for set in sets {
+                    let next = match result.take() {
+                        None => set,
+                        Some(r) => Cow::Owned(intersect_sorted(&r, &set)),
+                    };
+                    if next.is_empty() { return Some(next); }
+                    result = Some(next);
                 }
-            }
-            
-            let search_len = search_bytes.len() as u8;
-            
-            // Parallel: filter matching tags and flat_map their items
-            let mut matching: Vec<u32> = self.tag_char_index[best_ci]
-                .par_iter()
-                .filter_map(|&tag_idx| {
-                    let t = &self.tags_vec[tag_idx as usize];
-                    if t.len >= search_len {
-                        if (search_mask & t.mask) == search_mask {
-                            if is_subsequence(&search_bytes, &t.lower) {
-                                Some(&t.items[..])
-                            } else {
-                                None
-                            }
-                        } else {
-                            None
-                        }
-                    } else {
-                        None
-                    }
-                })
-                .flatten()
-                .copied()
-                .collect();
-            matching.sort_unstable();
-            matching.dedup();
-            
-            candidates = Some(match candidates {
-                None => matching,
-                Some(c) => intersect_sorted(&c, &matching),
-            });
-            if candidates.as_ref().map_or(true, |c| c.is_empty()) { 
-                return Vec::new(); 
-            }
-        }
-        
-        match candidates {
-            Some(c) => {
-                let done_flags = &self.done_flags;
-                // In fast_mode, limit results for performance (heuristic)
-                let limit = if self.fast_mode { 100 } else { usize::MAX };
                 
-                // Imperative approach for final collection
-                let mut result: Vec<&TodoItem> = Vec::with_capacity(c.len().min(limit));
-                // Natural order (ascending by index)
-                for &i in c.iter() {
-                    if !done_flags[i as usize] {
-                        result.push(&self.items[i as usize]);
-                        if result.len() >= limit { break; }
-                    }
+                // Negated children are subtracted instead of materializing their complement
+                for child in children {
+                    let SearchExpr::Not(inner) = child else { continue };
+                    let Some(excluded) = self.eval(inner) else { return Some(Cow::Borrowed(&[])) };
+                    let base = result.take().unwrap_or_else(|| Cow::Owned(self.live_ids()));
+                    result = Some(Cow::Owned(difference_sorted(&base, &excluded)));
                 }
                 result
             }
-            None => Vec::new(),
+            SearchExpr::Or(children) => {
+                let mut result: Option<Cow<[u32]>> = None;
+                for child in children {
+                    let set = self.eval(child)?;
+                    result = Some(match result.take() {
+                        None => set,
+                        Some(r) => Cow::Owned(union_sorted(&r, &set)),
+                    });
+                }
+                result
+            }
+            SearchExpr::Not(inner) => match self.eval(inner) {
+                None => Some(Cow::Borrowed(&[])),
+                Some(excluded) => Some(Cow::Owned(difference_sorted(&self.live_ids(), &excluded))),
+            },
+            SearchExpr::Due(op, spec) => Some(Cow::Owned(self.due_ids(*op, spec.resolve(self.today())))),
+            SearchExpr::Priority(p) => Some(Cow::Borrowed(&self.priorities[*p as usize])),
+            SearchExpr::Overdue => {
+                let mut ids = self.due_ids(DateOp::Before, self.today());
+                ids.retain(|&i| !self.done_flags[i as usize]);
+                Some(Cow::Owned(ids))
+            }
         }
     }
+    
+    /// Sorted ids of the items due `op` `date`
+    fn due_ids(&self, op: DateOp, date: Date) -> Vec<u32> {
+        let range = match op {
+            DateOp::Before => (Bound::Unbounded, Bound::Excluded(date)),
+            DateOp::OnOrBefore => (Bound::Unbounded, Bound::Included(date)),
+            DateOp::On => (Bound::Included(date), Bound::Included(date)),
+            DateOp::OnOrAfter => (Bound::Included(date), Bound::Unbounded),
+            DateOp::After => (Bound::Excluded(date), Bound::Unbounded),
+        };
+        let mut ids: Vec<u32> = self.due.range(range).flat_map(|(_, ids)| ids.iter().copied()).collect();
+        // Each item has one due date, so there is nothing to dedup
+        ids.sort_unstable();
+        ids
+    }
+    
+    fn live_ids(&self) -> Vec<u32> {
+        self.items.iter()
+            .enumerate()
+            .filter(|(_, item)| item.is_some())
+            .map(|(i, _)| i as u32)
+            .collect()
+    }
+    
+    /// Sorted ids of the items with a word (or tag) that matches `term` under the current match mode
+    fn match_term(&self, term: &str, is_tag: bool) -> Cow<'_, [u32]> {
+        let terms = if is_tag { &self.tags } else { &self.words };
+        terms.matching(term, self.match_mode)
+    }
+}
+
+/// The `window` of `keyed` ordered by key (descending if `desc`), ties by ascending index
+fn ranked<K: Ord>(mut keyed: Vec<(K, &TodoItem)>, desc: bool, window: Window) -> SearchPage<(K, &TodoItem)> {
+    let total = keyed.len();
+    let cmp = |a: &(K, &TodoItem), b: &(K, &TodoItem)| {
+        let by_key = if desc { b.0.cmp(&a.0) } else { a.0.cmp(&b.0) };
+        by_key.then(a.1.index.0.cmp(&b.1.index.0))
+    };
+    // Only the items up to the end of the page need sorting
+    let end = window.skip.saturating_add(window.take).min(total);
+    if end < total {
+        if end > 0 { keyed.select_nth_unstable_by(end - 1, cmp); }
+        keyed.truncate(end);
+    }
+    keyed.sort_unstable_by(cmp);
+    SearchPage { items: keyed.into_iter().skip(window.skip).collect(), total }
+}
+
+/// The words and tags `expr` looks for, leaving out negated ones
+fn positive_terms<'e>(expr: &'e SearchExpr, out: &mut Vec<(&'e str, bool)>) {
+    match expr {
+        SearchExpr::Word(w) if !w.0.is_empty() => out.push((&w.0, false)),
+        SearchExpr::Tag(t) if !t.0.is_empty() => out.push((&t.0, true)),
+        SearchExpr::And(children) | SearchExpr::Or(children) => {
+            for child in children { positive_terms(child, out); }
+        }
+        _ => {}
+    }
+}
+
+fn unindex_due(due: &mut BTreeMap<Date, Vec<u32>>, date: Date, item_idx: u32) {
+    if let Some(ids) = due.get_mut(&date) {
+        remove_sorted(ids, item_idx);
+        if ids.is_empty() { due.remove(&date); }
+    }
+}
+
+/// Removes `id` from a sorted id list, if it is there
+fn remove_sorted(ids: &mut Vec<u32>, id: u32) {
+    if let Ok(pos) = ids.binary_search(&id) {
+        ids.remove(pos);
+    }
 }
 
 #[inline]
@@ -471,3 +842,38 @@ fn intersect_sorted(a: &[u32], b: &[u32]) -> Vec<u32> {
     }
     result
 }
+
+#[inline]
+fn union_sorted(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = Vec::with_capacity(a.len() + b.len());
+    let mut i = 0;
+    let mut j = 0;
+    while i < a.len() && j < b.len() {
+        if a[i] < b[j] {
+            result.push(a[i]);
+            i += 1;
+        } else if a[i] > b[j] {
+            result.push(b[j]);
+            j += 1;
+        } else {
+            result.push(a[i]);
+            i += 1;
+            j += 1;
+        }
+    }
+    result.extend_from_slice(&a[i..]);
+    result.extend_from_slice(&b[j..]);
+    result
+}
+
+/// Elements of `a` that are not in `b`
+#[inline]
+fn difference_sorted(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = Vec::with_capacity(a.len());
+    let mut j = 0;
+    for &x in a {
+        while j < b.len() && b[j] < x { j += 1; }
+        if j == b.len() || b[j] != x { result.push(x); }
+    }
+    result
+}
@@ -0,0 +1,208 @@
+//! CSV import and export with the columns `index,description,tags,done,due,priority`.
+//!
+//! Tags are space-separated without the leading `#`, `done` is `true` or `false`, `due` is
+//! empty or `YYYY-MM-DD`, and `priority` is empty, `low`, `medium` or `high`. The `due` and
+//! `priority` columns may be left out on import. Surrounding spaces are ignored in every
+//! column but `description`, which is kept as written.
+//! Import keeps the original indices; gaps between them become deleted items.
+use std::fmt::{self, Display};
+use std::io::{self, Read, Write};
+
+use serde::Deserialize;
+
+use super::{is_tag_name, Priority, TodoList};
+use crate::{Date, Description, Tag};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CsvRowError {
+    /// 1-based line of the record in the file, counting the header
+    pub line: u64,
+    pub message: String,
+}
+
+impl Display for CsvRowError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+#[derive(Debug)]
+pub enum CsvError {
+    Io(io::Error),
+    /// Every row that could not be imported; nothing is imported if there is any
+    Rows(Vec<CsvRowError>),
+}
+
+impl Display for CsvError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "CSV I/O error: {e}"),
+            Self::Rows(rows) => write!(f, "{} invalid CSV row(s)", rows.len()),
+        }
+    }
+}
+
+impl std::error::Error for CsvError {}
+
+impl From<io::Error> for CsvError {
+    fn from(e: io::Error) -> Self { Self::Io(e) }
+}
+
+/// Columns an imported file must have; export adds `due` and `priority`
+const COLUMNS: [&str; 4] = ["index", "description", "tags", "done"];
+/// Most indices that may be missing from an imported file
+const MAX_GAPS: usize = 1 << 20;
+
+#[derive(Deserialize)]
+struct CsvRow {
+    index: String,
+    description: String,
+    tags: String,
+    done: String,
+    #[serde(default)]
+    due: String,
+    #[serde(default)]
+    priority: String,
+}
+
+struct ParsedRow {
+    index: u64,
+    description: Description,
+    tags: Vec<Tag>,
+    done: bool,
+    due: Option<Date>,
+    priority: Option<Priority>,
+}
+
+impl TodoList {
+    /// Writes every live item as a CSV row, in index order.
+    /// # Errors
+    /// Returns an I/O error if writing fails.
+    pub fn export_csv<W: Write>(&self, out: W) -> io::Result<()> {
+        let mut w = csv::Writer::from_writer(out);
+        w.write_record(COLUMNS.iter().chain(&["due", "priority"]))?;
+        let mut buffer = itoa::Buffer::new();
+        for item in self.items.iter().flatten() {
+            let tags = item.tags.iter().map(|t| t.0.as_str()).collect::<Vec<_>>().join(" ");
+            let done = if item.done { "true" } else { "false" };
+            let due = item.due.map(|d| d.to_string()).unwrap_or_default();
+            let priority = item.priority.map_or("", Priority::as_str);
+            w.write_record([buffer.format(item.index.0), &item.description.0, &tags, done, &due, priority])?;
+        }
+        w.flush()
+    }
+
+    /// Builds a fresh list from CSV written by `export_csv` (or a spreadsheet with the same columns),
+    /// keeping each row's index and done status.
+    /// # Errors
+    /// Fails on I/O errors, or with every invalid row if any row cannot be imported.
+    pub fn import_csv<R: Read>(input: R) -> Result<Self, CsvError> {
+        let mut reader = csv::ReaderBuilder::new().trim(csv::Trim::Headers).from_reader(input);
+        let headers = reader.headers().map_err(io::Error::from)?.clone();
+        let missing: Vec<_> = COLUMNS.iter().filter(|c| !headers.iter().any(|h| h == **c)).collect();
+        if !missing.is_empty() {
+            let message = format!("missing column(s) {}", missing.iter().map(|c| format!("`{c}`")).collect::<Vec<_>>().join(", "));
+            return Err(CsvError::Rows(vec![CsvRowError { line: 1, message }]));
+        }
+
+        let mut rows = Vec::new();
+        let mut errors = Vec::new();
+        let mut record = csv::StringRecord::new();
+        loop {
+            match reader.read_record(&mut record) {
+                Ok(false) => break,
+                Ok(true) => {
+                    let line = record.position().map_or(0, csv::Position::line);
+                    let parsed = record.deserialize::<CsvRow>(Some(&headers)).map_err(|e| csv_message(&e)).and_then(parse_row);
+                    match parsed {
+                        Ok(row) => rows.push((line, row)),
+                        Err(message) => errors.push(CsvRowError { line, message }),
+                    }
+                }
+                Err(e) if matches!(e.kind(), csv::ErrorKind::Io(_)) => return Err(CsvError::Io(e.into())),
+                Err(e) => {
+                    let line = e.position().map_or(0, csv::Position::line);
+                    errors.push(CsvRowError { line, message: csv_message(&e) });
+                }
+            }
+        }
+
+        rows.sort_by_key(|(_, row)| row.index);
+        for pair in rows.windows(2) {
+            if pair[0].1.index == pair[1].1.index {
+                errors.push(CsvRowError { line: pair[1].0, message: format!("duplicate index {}", pair[1].1.index) });
+            }
+        }
+        // Every missing index costs a tombstone, so a stray huge index must not allocate millions of them
+        if let Some((line, row)) = rows.last() {
+            if row.index >= (rows.len() + MAX_GAPS) as u64 {
+                errors.push(CsvRowError { line: *line, message: format!("index {} leaves too many gaps", row.index) });
+            }
+        }
+        if !errors.is_empty() {
+            errors.sort_by_key(|e| e.line);
+            return Err(CsvError::Rows(errors));
+        }
+
+        let mut tl = Self::new();
+        for (_, row) in rows {
+            // Indices missing from the file are kept as deleted items
+            while tl.top_index < row.index {
+                tl.items.push(None);
+                tl.done_flags.push(false);
+                tl.top_index += 1;
+            }
+            let idx = tl.push(row.description, row.tags);
+            if row.done {
+                tl.done_with_index(idx);
+            }
+            if row.due.is_some() {
+                tl.set_due(idx, row.due);
+            }
+            if row.priority.is_some() {
+                tl.set_priority(idx, row.priority);
+            }
+        }
+        Ok(tl)
+    }
+}
+
+fn csv_message(e: &csv::Error) -> String {
+    match e.kind() {
+        csv::ErrorKind::Deserialize { err, .. } => err.to_string(),
+        csv::ErrorKind::UnequalLengths { expected_len, len, .. } => format!("expected {expected_len} fields, found {len}"),
+        csv::ErrorKind::Utf8 { .. } => "invalid UTF-8".into(),
+        _ => e.to_string(),
+    }
+}
+
+fn parse_row(row: CsvRow) -> Result<ParsedRow, String> {
+    let index = row.index.trim().parse().map_err(|_| format!("invalid index `{}`", row.index))?;
+    if row.description.chars().any(|c| c.is_control() && c != '\t') {
+        return Err("description contains control characters".into());
+    }
+    let tags = row.tags.split_whitespace()
+        .map(|t| {
+            let t = t.strip_prefix('#').unwrap_or(t);
+            if !is_tag_name(t) {
+                return Err(format!("invalid tag `{t}`"));
+            }
+            Ok(Tag::new(t))
+        })
+        .collect::<Result<_, _>>()?;
+    let done = match row.done.trim().to_ascii_lowercase().as_str() {
+        "true" | "1" | "yes" => true,
+        "false" | "0" | "no" | "" => false,
+        _ => return Err(format!("invalid done value `{}` (expected true or false)", row.done)),
+    };
+    let due = match row.due.trim() {
+        "" => None,
+        d => Some(d.parse()?),
+    };
+    let priority = match row.priority.trim().to_ascii_lowercase().as_str() {
+        "" => None,
+        p => Some(p.parse()?),
+    };
+    Ok(ParsedRow { index, description: Description::new(&row.description), tags, done, due, priority })
+}
+
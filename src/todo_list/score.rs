@@ -0,0 +1,54 @@
+//! Relevance scores for search terms, used by `sort:relevance` and shown with `--scores`.
+//!
+//! A term scores against a word by its best subsequence alignment: every matched character earns
+//! points, with bonuses for characters that directly follow the previous match and for characters
+//! that start the word or one of its `-` parts. Prefix and exact matches get a further boost, and
+//! every word character left unmatched costs a point, so `bd` ranks `bread` above `birthday`.
+
+/// Every matched character
+const MATCH: u32 = 16;
+/// A matched character right after the previous one
+const CONSECUTIVE: u32 = 8;
+/// A matched character at the start of the word or of a `-` separated part
+const WORD_START: u32 = 12;
+/// The term is a prefix of the word
+const PREFIX: u32 = 16;
+/// The term is the whole word
+const EXACT: u32 = 48;
+/// Cap on the penalty for unmatched characters, so long words still rank by their matches
+const MAX_UNMATCHED_PENALTY: u32 = 16;
+
+/// Tag matches count one and a half times as much as word matches, since tags are chosen on purpose
+pub(super) fn weighted(score: u32, is_tag: bool) -> u32 {
+    if is_tag { score + score / 2 } else { score }
+}
+
+/// Score of lowercase `term` against lowercase `word`, or `None` if it is not a subsequence of it
+pub(super) fn term_score(term: &str, word: &str) -> Option<u32> {
+    let term: Vec<char> = term.chars().collect();
+    let word: Vec<char> = word.chars().collect();
+    if term.is_empty() || term.len() > word.len() { return None; }
+
+    let starts_part = |j: usize| j == 0 || word[j - 1] == '-';
+    // best[j]: best score so far with the latest term character matched at word[j]
+    let mut best: Vec<Option<u32>> = word.iter().enumerate()
+        .map(|(j, &c)| (c == term[0]).then(|| MATCH + if starts_part(j) { WORD_START } else { 0 }))
+        .collect();
+    for &t in &term[1..] {
+        let mut next = vec![None; word.len()];
+        // Best alignment ending two or more characters before `j`
+        let mut earlier: Option<u32> = None;
+        for j in 1..word.len() {
+            if j >= 2 { earlier = earlier.max(best[j - 2]); }
+            if word[j] != t { continue; }
+            let consecutive = best[j - 1].map(|s| s + CONSECUTIVE);
+            next[j] = consecutive.max(earlier).map(|s| s + MATCH + if starts_part(j) { WORD_START } else { 0 });
+        }
+        best = next;
+    }
+
+    let mut score = best.into_iter().flatten().max()?;
+    if word.starts_with(&term) { score += PREFIX; }
+    if word.len() == term.len() { score += EXACT; }
+    Some(score.saturating_sub(((word.len() - term.len()) as u32).min(MAX_UNMATCHED_PENALTY)))
+}
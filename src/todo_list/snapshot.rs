@@ -0,0 +1,248 @@
+//! Binary snapshot of a `TodoList`, including its prebuilt search tables.
+//!
+//! Layout: `[magic: 8][version: u32][checksum: u32][payload_len: u64][payload]`.
+//! The payload starts with the journal position the snapshot was taken at, if any.
+//! Term tables are stored with their masks and character and pair postings, so
+//! loading reads them back in bulk; only the term lookup maps are rebuilt.
+//! At a million items, loading is about four times faster than replaying the journal with a
+//! 200k-word vocabulary, and about twice as fast when nearly every word is distinct
+//! (see the ignored `test_snapshot_load_beats_replay` benchmark).
+use std::fmt::{self, Display};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use super::term_index::TermIndex;
+use super::{Metadata, TodoList, TodoItem};
+use crate::codec::{self, Reader};
+use crate::{Date, Description, Index, Tag};
+
+const MAGIC: &[u8; 8] = b"TODOSNAP";
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+const ITEM_DELETED: u8 = 0;
+const ITEM_OPEN: u8 = 1;
+const ITEM_DONE: u8 = 2;
+const HEADER_LEN: usize = 24;
+
+/// Where in a journal a snapshot was taken: the journal's length and the frame header of its
+/// last record, which tells a later open whether the journal still holds what the snapshot does.
+/// All zero for snapshots not taken from a journal.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct JournalMark {
+    pub(crate) len: u64,
+    pub(crate) last_frame: [u8; 8],
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    NotASnapshot,
+    VersionMismatch { found: u32, expected: u32 },
+    ChecksumMismatch,
+    Corrupt,
+}
+
+impl Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "snapshot I/O error: {e}"),
+            Self::NotASnapshot => write!(f, "file is not a todo snapshot"),
+            Self::VersionMismatch { found, expected } => {
+                write!(f, "unsupported snapshot format version {found} (expected {expected})")
+            }
+            Self::ChecksumMismatch => write!(f, "snapshot checksum mismatch"),
+            Self::Corrupt => write!(f, "snapshot payload is corrupt"),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl From<io::Error> for SnapshotError {
+    fn from(e: io::Error) -> Self { Self::Io(e) }
+}
+
+impl TodoList {
+    /// Writes the list and its search index to `path`, replacing any existing file atomically.
+    /// # Errors
+    /// Returns an I/O error if the snapshot cannot be written.
+    pub fn save_snapshot<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        self.save_snapshot_marked(path.as_ref(), JournalMark::default())
+    }
+
+    /// Like `save_snapshot`, recording that the list holds the journal up to `mark`
+    pub(crate) fn save_snapshot_marked(&self, path: &Path, mark: JournalMark) -> io::Result<()> {
+        let payload = self.encode_snapshot(mark);
+
+        let mut header = Vec::with_capacity(HEADER_LEN);
+        header.extend_from_slice(MAGIC);
+        codec::put_u32(&mut header, SNAPSHOT_VERSION);
+        codec::put_u32(&mut header, codec::checksum_wide(&payload));
+        codec::put_u64(&mut header, payload.len() as u64);
+
+        // Next to the target, so the rename stays on one file system
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        let mut out = BufWriter::with_capacity(1 << 20, File::create(&tmp)?);
+        out.write_all(&header)?;
+        out.write_all(&payload)?;
+        out.into_inner().map_err(io::IntoInnerError::into_error)?.sync_all()?;
+        fs::rename(tmp, path)?;
+        sync_parent(path)
+    }
+
+    /// Loads a list previously written by `save_snapshot`.
+    /// # Errors
+    /// Fails on I/O errors, a foreign file, a format version mismatch or a bad checksum.
+    pub fn load_snapshot<P: AsRef<Path>>(path: P) -> Result<Self, SnapshotError> {
+        Self::load_snapshot_marked(path.as_ref()).map(|(tl, _)| tl)
+    }
+
+    /// Like `load_snapshot`, also returning the journal position the snapshot was taken at
+    pub(crate) fn load_snapshot_marked(path: &Path) -> Result<(Self, JournalMark), SnapshotError> {
+        let data = fs::read(path)?;
+        if data.len() < HEADER_LEN || &data[..8] != MAGIC {
+            return Err(SnapshotError::NotASnapshot);
+        }
+
+        let mut header = Reader::new(&data[8..HEADER_LEN]);
+        let (version, sum, len) = match (header.u32(), header.u32(), header.u64()) {
+            (Some(v), Some(s), Some(l)) => (v, s, l),
+            _ => return Err(SnapshotError::NotASnapshot),
+        };
+        if version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::VersionMismatch { found: version, expected: SNAPSHOT_VERSION });
+        }
+
+        let payload = &data[HEADER_LEN..];
+        if payload.len() as u64 != len {
+            return Err(SnapshotError::Corrupt);
+        }
+        if codec::checksum_wide(payload) != sum {
+            return Err(SnapshotError::ChecksumMismatch);
+        }
+        Self::decode_snapshot(payload).ok_or(SnapshotError::Corrupt)
+    }
+
+    fn encode_snapshot(&self, mark: JournalMark) -> Vec<u8> {
+        let mut buf = Vec::new();
+        codec::put_u64(&mut buf, mark.len);
+        buf.extend_from_slice(&mark.last_frame);
+        codec::put_u64(&mut buf, self.top_index);
+        codec::put_u64(&mut buf, self.items.len() as u64);
+        for item in &self.items {
+            let Some(item) = item else {
+                codec::put_u8(&mut buf, ITEM_DELETED);
+                continue;
+            };
+            codec::put_u8(&mut buf, if item.done { ITEM_DONE } else { ITEM_OPEN });
+            codec::put_str(&mut buf, &item.description.0);
+            codec::put_u32(&mut buf, item.tags.len() as u32);
+            for tag in &item.tags { codec::put_str(&mut buf, &tag.0); }
+            encode_meta(&mut buf, item.meta.as_deref());
+            // 0 for no due date
+            codec::put_u32(&mut buf, item.due.map_or(0, Date::to_packed));
+            codec::put_priority(&mut buf, item.priority);
+        }
+        self.words.encode(&mut buf);
+        self.tags.encode(&mut buf);
+        buf
+    }
+
+    fn decode_snapshot(payload: &[u8]) -> Option<(Self, JournalMark)> {
+        let mut r = Reader::new(payload);
+        let mut mark = JournalMark { len: r.u64()?, ..JournalMark::default() };
+        mark.last_frame.copy_from_slice(r.bytes(8)?);
+        let mut tl = Self::new();
+        tl.top_index = r.u64()?;
+
+        let count = r.u64()? as usize;
+        tl.items.reserve(count.min(payload.len()));
+        tl.done_flags.reserve(count.min(payload.len()));
+        for i in 0..count {
+            let done = match r.u8()? {
+                ITEM_DELETED => {
+                    tl.items.push(None);
+                    tl.done_flags.push(false);
+                    continue;
+                }
+                ITEM_OPEN => false,
+                ITEM_DONE => true,
+                _ => return None,
+            };
+            let description = Description::new(r.str()?);
+            let tag_count = r.u32()? as usize;
+            let mut tags = Vec::with_capacity(tag_count.min(64));
+            for _ in 0..tag_count { tags.push(Tag::new(r.str()?)); }
+
+            let mut item = TodoItem::new(Index(i as u64), description, tags);
+            item.done = done;
+            item.meta = decode_meta(&mut r)?.map(Box::new);
+            item.due = match r.u32()? {
+                0 => None,
+                packed => Some(Date::from_packed(packed)?),
+            };
+            if let Some(due) = item.due {
+                tl.due.entry(due).or_default().push(i as u32);
+            }
+            item.priority = r.priority()?;
+            if let Some(p) = item.priority {
+                tl.priorities[p as usize].push(i as u32);
+            }
+            tl.items.push(Some(item));
+            tl.done_flags.push(done);
+            tl.live_count += 1;
+            tl.done_count += usize::from(done);
+        }
+        if tl.top_index != count as u64 { return None; }
+
+        // Postings must point at live items
+        let live = |i: u32| tl.items.get(i as usize).is_some_and(Option::is_some);
+        let words = TermIndex::decode(&mut r, live)?;
+        let tags = TermIndex::decode(&mut r, live)?;
+        tl.words = words;
+        tl.tags = tags;
+        r.is_empty().then_some((tl, mark))
+    }
+}
+
+/// Syncs the directory holding `path`, so a rename into it survives a crash
+fn sync_parent(path: &Path) -> io::Result<()> {
+    // Windows has no way to open a directory as a file
+    #[cfg(unix)]
+    {
+        let dir = path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        File::open(dir)?.sync_all()?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+/// `[present: u8]` (bit 0 priority, bit 1 created, bit 2 completed) followed by the present fields
+fn encode_meta(buf: &mut Vec<u8>, meta: Option<&Metadata>) {
+    let Some(meta) = meta else {
+        codec::put_u8(buf, 0);
+        return;
+    };
+    codec::put_u8(buf, u8::from(meta.priority.is_some()) | u8::from(meta.created.is_some()) << 1 | u8::from(meta.completed.is_some()) << 2);
+    if let Some(p) = meta.priority { codec::put_u8(buf, p as u8); }
+    if let Some(d) = &meta.created { codec::put_str(buf, d); }
+    if let Some(d) = &meta.completed { codec::put_str(buf, d); }
+}
+
+fn decode_meta(r: &mut Reader) -> Option<Option<Metadata>> {
+    let present = r.u8()?;
+    if present == 0 { return Some(None); }
+    if present > 7 { return None; }
+    let priority = if present & 1 != 0 {
+        match r.u8()? {
+            p @ b'A'..=b'Z' => Some(char::from(p)),
+            _ => return None,
+        }
+    } else { None };
+    let created = if present & 2 != 0 { Some(r.str()?.to_owned()) } else { None };
+    let completed = if present & 4 != 0 { Some(r.str()?.to_owned()) } else { None };
+    Some(Some(Metadata { priority, created, completed }))
+}
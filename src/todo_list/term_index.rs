@@ -0,0 +1,589 @@
+//! Term table for words or tags, with character, ordered-pair and ordered-quad postings.
+//!
+//! A subsequence (or prefix) match of `q` must contain every run of four consecutive
+//! query characters as an ordered quad (gaps allowed), so intersecting the quad postings
+//! narrows a query of four or more characters to a handful of candidates that are then
+//! verified. With 26^4 lowercase quads, each list holds a tiny share of the terms, and the
+//! rarest of the query's quads is what a search pays for. Shorter queries use the ordered
+//! pairs `(q[i], q[i+1])` the same way.
+//!
+//! Quads grow with the fourth power of a term's length, so only terms up to
+//! `MAX_QUAD_TERM_LEN` get them; longer terms up to `MAX_PAIR_TERM_LEN` get pair postings
+//! of their own, and terms longer than that are verified one by one.
+//!
+//! Adding a term only registers it in the table. The next search brings the postings up
+//! to date, sizing the lists for a large batch of new terms up front, so a long run of
+//! adds never pays for scattered pushes into hundreds of thousands of growing lists.
+use std::borrow::Cow;
+use std::collections::hash_map::{Entry, HashMap, RandomState};
+use std::hash::{BuildHasher, BuildHasherDefault, Hash, Hasher};
+use std::sync::{Mutex, PoisonError};
+use rayon::prelude::*;
+
+use super::{intersect_sorted, with_lower, MatchMode};
+use crate::codec::{self, Reader};
+
+/// Below this many candidates, further intersections cost more than verifying
+const SMALL_CANDIDATES: usize = 16;
+/// Skip a posting list that is this many times larger than the current candidates
+const MAX_SKEW: usize = 16;
+
+/// Longest term, in characters, whose ordered quads are indexed
+const MAX_QUAD_TERM_LEN: usize = 12;
+/// Longest term, in characters, whose character pairs are indexed
+const MAX_PAIR_TERM_LEN: usize = 32;
+/// Catching up on at least this many terms sizes the quad lists before filling them
+const BULK_TERMS: usize = 4096;
+/// Candidate lists shorter than this are verified on the calling thread
+const PARALLEL_CANDIDATES: usize = 4096;
+
+/// Check if `sub` is a subsequence of `text` (characters appear in order but not necessarily contiguous)
+#[inline(always)]
+fn is_subsequence(sub: &str, text: &str) -> bool {
+    // ASCII bytes never occur inside multi-byte UTF-8 sequences, so bytes can stand in for chars
+    if sub.is_ascii() {
+        let mut text = text.bytes();
+        return sub.bytes().all(|c| text.any(|t| t == c));
+    }
+    let mut text = text.chars();
+    sub.chars().all(|c| text.any(|t| t == c))
+}
+
+/// Mask bit for a character: a-z and 0-9 get their own bits, anything else shares the rest
+#[inline(always)]
+fn char_bit(c: char) -> u64 {
+    match c {
+        'a'..='z' => 1 << (c as u32 - 'a' as u32),
+        '0'..='9' => 1 << (26 + c as u32 - '0' as u32),
+        _ => 1 << (36 + c as u32 % 28),
+    }
+}
+
+/// Bitmask of which characters are present in the string
+#[inline(always)]
+fn char_mask(s: &str) -> u64 {
+    s.chars().fold(0, |mask, c| mask | char_bit(c))
+}
+
+/// Posting-list key with a dense slot when it is made of ASCII characters
+trait DenseKey: Hash + Eq + Ord + Copy {
+    const DENSE_LEN: usize;
+    fn dense_slot(self) -> Option<usize>;
+    fn put(self, buf: &mut Vec<u8>);
+    fn read(r: &mut Reader) -> Option<Self>;
+}
+
+impl DenseKey for char {
+    const DENSE_LEN: usize = 128;
+    fn dense_slot(self) -> Option<usize> { self.is_ascii().then_some(self as usize) }
+    fn put(self, buf: &mut Vec<u8>) { codec::put_u32(buf, self as u32); }
+    fn read(r: &mut Reader) -> Option<Self> { char::from_u32(r.u32()?) }
+}
+
+impl DenseKey for (char, char) {
+    const DENSE_LEN: usize = 128 * 128;
+    fn dense_slot(self) -> Option<usize> {
+        (self.0.is_ascii() && self.1.is_ascii()).then_some(self.0 as usize * 128 + self.1 as usize)
+    }
+    fn put(self, buf: &mut Vec<u8>) {
+        self.0.put(buf);
+        self.1.put(buf);
+    }
+    fn read(r: &mut Reader) -> Option<Self> { Some((char::read(r)?, char::read(r)?)) }
+}
+
+impl DenseKey for [char; 4] {
+    /// Lowercase ASCII letters only, which is what plain words fold to
+    const DENSE_LEN: usize = 26 * 26 * 26 * 26;
+    fn dense_slot(self) -> Option<usize> {
+        self.iter().try_fold(0, |slot, &c| c.is_ascii_lowercase().then(|| slot * 26 + (c as usize - 'a' as usize)))
+    }
+    fn put(self, buf: &mut Vec<u8>) {
+        for c in self { c.put(buf); }
+    }
+    fn read(r: &mut Reader) -> Option<Self> { Some([char::read(r)?, char::read(r)?, char::read(r)?, char::read(r)?]) }
+}
+
+/// Term ids renumbered by `remap`, leaving out the ones mapped to `u32::MAX`
+fn remapped<'a>(ids: &'a [u32], remap: &'a [u32]) -> impl Iterator<Item = u32> + 'a {
+    ids.iter().map(move |&id| remap[id as usize]).filter(|&id| id != u32::MAX)
+}
+
+/// Reads an id list, which must be strictly ascending with every id below `bound`
+fn read_ids(r: &mut Reader, bound: u32) -> Option<Vec<u32>> {
+    let ids = r.u32s()?;
+    let valid = ids.windows(2).all(|w| w[0] < w[1]) && ids.last().is_none_or(|&id| id < bound);
+    valid.then_some(ids)
+}
+
+/// Appends `term_idx` unless it already ends the list
+fn push_new(list: &mut Vec<u32>, term_idx: u32) {
+    if list.last() != Some(&term_idx) {
+        list.push(term_idx);
+    }
+}
+
+/// Term postings per key; ASCII keys skip hashing, which keeps adds of plain words cheap
+struct Postings<K> {
+    dense: Vec<Vec<u32>>,
+    sparse: HashMap<K, Vec<u32>>,
+}
+
+impl<K> Default for Postings<K> {
+    fn default() -> Self { Self { dense: Vec::new(), sparse: HashMap::new() } }
+}
+
+impl<K: DenseKey> Postings<K> {
+    fn get(&self, key: K) -> &[u32] {
+        let list = match key.dense_slot() {
+            Some(slot) => self.dense.get(slot),
+            None => self.sparse.get(&key),
+        };
+        list.map_or(&[], |l| &l[..])
+    }
+
+    /// Terms are indexed in increasing id order, so a repeated push for the same term is skipped
+    fn push(&mut self, key: K, term_idx: u32) {
+        match key.dense_slot() {
+            Some(slot) => self.push_dense(slot, term_idx),
+            None => push_new(self.sparse.entry(key).or_default(), term_idx),
+        }
+    }
+
+    fn push_dense(&mut self, slot: usize, term_idx: u32) {
+        if self.dense.is_empty() {
+            self.dense.resize_with(K::DENSE_LEN, Vec::new);
+        }
+        push_new(&mut self.dense[slot], term_idx);
+    }
+
+    /// Makes room for `counts[slot]` more ids in each dense list
+    fn reserve(&mut self, counts: &[u32]) {
+        if self.dense.is_empty() {
+            self.dense.resize_with(K::DENSE_LEN, Vec::new);
+        }
+        for (list, &count) in self.dense.iter_mut().zip(counts) {
+            list.reserve(count as usize);
+        }
+    }
+
+    /// Renumbers term ids after compaction, dropping the ones mapped to `u32::MAX`
+    fn remap(&mut self, remap: &[u32]) {
+        for list in self.dense.iter_mut().chain(self.sparse.values_mut()) {
+            list.retain(|&id| remap[id as usize] != u32::MAX);
+            for id in list.iter_mut() { *id = remap[*id as usize]; }
+        }
+        self.sparse.retain(|_, list| !list.is_empty());
+    }
+
+    /// Writes the lists with term ids renumbered by `remap`; sparse keys are sorted so equal lists encode equally
+    fn encode(&self, buf: &mut Vec<u8>, remap: &[u32]) {
+        codec::put_u32(buf, self.dense.len() as u32);
+        for list in &self.dense {
+            codec::put_u32s(buf, remapped(list, remap));
+        }
+        let mut sparse: Vec<_> = self.sparse.iter()
+            .filter(|(_, list)| remapped(list, remap).next().is_some())
+            .collect();
+        sparse.sort_unstable_by_key(|(key, _)| **key);
+        codec::put_u32(buf, sparse.len() as u32);
+        for (key, list) in sparse {
+            key.put(buf);
+            codec::put_u32s(buf, remapped(list, remap));
+        }
+    }
+
+    /// Reads what `encode` wrote for a table of `term_count` terms
+    fn decode(r: &mut Reader, term_count: u32) -> Option<Self> {
+        let dense_len = r.u32()? as usize;
+        if dense_len != 0 && dense_len != K::DENSE_LEN { return None; }
+        let dense = (0..dense_len).map(|_| read_ids(r, term_count)).collect::<Option<Vec<_>>>()?;
+
+        let sparse_len = r.u32()? as usize;
+        let mut sparse = HashMap::with_capacity(sparse_len.min(r.remaining()));
+        for _ in 0..sparse_len {
+            let key = K::read(r)?;
+            let list = read_ids(r, term_count)?;
+            // ASCII keys belong in the dense slots, and a repeated key was not written by `encode`
+            if key.dense_slot().is_some() || list.is_empty() || sparse.insert(key, list).is_some() { return None; }
+        }
+        Some(Self { dense, sparse })
+    }
+}
+
+struct WordInfo {
+    lower: Box<str>,
+    mask: u64,
+    // Length in characters, saturated at 255
+    len: u8,
+    items: Vec<u32>,
+}
+
+/// Passes on a hash that was already computed
+#[derive(Default)]
+struct PrehashedHasher(u64);
+
+impl Hasher for PrehashedHasher {
+    fn write(&mut self, _: &[u8]) { unreachable!("only u64 hashes are keys") }
+    fn write_u64(&mut self, hash: u64) { self.0 = hash; }
+    fn finish(&self) -> u64 { self.0 }
+}
+
+/// Term ids by a hash of the term, so that growing the table never reads the terms back
+#[derive(Default)]
+struct TermMap {
+    hasher: RandomState,
+    ids: HashMap<u64, u32, BuildHasherDefault<PrehashedHasher>>,
+    // Terms whose hash an earlier term already took
+    collided: HashMap<Box<str>, u32>,
+}
+
+impl TermMap {
+    fn get(&self, terms: &[WordInfo], lower: &str) -> Option<u32> {
+        match self.ids.get(&self.hasher.hash_one(lower)) {
+            Some(&id) if &*terms[id as usize].lower == lower => Some(id),
+            _ if self.collided.is_empty() => None,
+            _ => self.collided.get(lower).copied(),
+        }
+    }
+
+    /// Adds a term that `get` does not find
+    fn insert(&mut self, lower: &str, term_idx: u32) {
+        match self.ids.entry(self.hasher.hash_one(lower)) {
+            Entry::Vacant(entry) => { entry.insert(term_idx); }
+            Entry::Occupied(_) => { self.collided.insert(lower.into(), term_idx); }
+        }
+    }
+
+    /// Renumbers term ids after compaction, dropping the ones mapped to `u32::MAX`
+    fn remap(&mut self, remap: &[u32]) {
+        let keep = |id: &mut u32| {
+            *id = remap[*id as usize];
+            *id != u32::MAX
+        };
+        self.ids.retain(|_, id| keep(id));
+        self.collided.retain(|_, id| keep(id));
+    }
+}
+
+/// Search postings for the terms below `indexed`; the terms added since wait for the next search
+#[derive(Default)]
+struct TermPostings {
+    indexed: u32,
+    // Character -> terms containing it
+    chars: Postings<char>,
+    // Ordered character pair (a somewhere before b) -> terms up to `MAX_QUAD_TERM_LEN` containing it
+    pairs: Postings<(char, char)>,
+    // Ordered four characters (in that order, gaps allowed) -> terms up to `MAX_QUAD_TERM_LEN` containing them
+    quads: Postings<[char; 4]>,
+    // Ordered character pair -> longer terms, up to `MAX_PAIR_TERM_LEN`, containing it
+    wide_pairs: Postings<(char, char)>,
+    // Sorted ids of the terms too long for pair postings
+    long: Vec<u32>,
+}
+
+#[derive(Default)]
+pub(super) struct TermIndex {
+    terms: Vec<WordInfo>,
+    map: TermMap,
+    // Locked by searches, which index the terms added since the previous one
+    postings: Mutex<TermPostings>,
+}
+
+/// Calls `f` with every ordered pair of `chars`
+fn for_each_pair(chars: &[char], mut f: impl FnMut((char, char))) {
+    for (i, &b) in chars.iter().enumerate() {
+        for &a in &chars[..i] {
+            f((a, b));
+        }
+    }
+}
+
+/// Calls `f` with every ordered quad of `chars`
+fn for_each_quad(chars: &[char], mut f: impl FnMut([char; 4])) {
+    let n = chars.len();
+    for i in 0..n {
+        for j in i + 1..n {
+            for k in j + 1..n {
+                for l in k + 1..n {
+                    f([chars[i], chars[j], chars[k], chars[l]]);
+                }
+            }
+        }
+    }
+}
+
+/// Intersects the lists, smallest first, until the candidates are few enough to verify
+fn narrow<'a>(mut lists: Vec<&'a [u32]>) -> Cow<'a, [u32]> {
+    lists.sort_unstable_by_key(|l| l.len());
+    let Some((first, rest)) = lists.split_first() else { return Cow::Borrowed(&[]) };
+    let mut result = Cow::Borrowed(*first);
+    for list in rest {
+        if result.len() < SMALL_CANDIDATES || list.len() > result.len() * MAX_SKEW { break; }
+        result = Cow::Owned(intersect_sorted(&result, list));
+    }
+    result
+}
+
+impl TermPostings {
+    /// Indexes the terms added since the last call
+    fn catch_up(&mut self, terms: &[WordInfo]) {
+        let start = self.indexed as usize;
+        let bulk = terms.len() - start >= BULK_TERMS;
+        if bulk {
+            self.bulk_quads(&terms[start..], start as u32);
+        }
+        let mut chars = Vec::new();
+        for (term_idx, term) in (start as u32..).zip(&terms[start..]) {
+            chars.clear();
+            chars.extend(term.lower.chars());
+            self.add(&chars, term_idx, !bulk);
+        }
+        self.indexed = terms.len() as u32;
+    }
+
+    /// Fills the quad lists of many new terms at once. Each list is sized up front, and the
+    /// pushes go one slice of the key space at a time, so the lists being filled sit close
+    /// together in memory instead of scattered over all of them.
+    fn bulk_quads(&mut self, terms: &[WordInfo], first: u32) {
+        const SLICE_BITS: u32 = 13;
+        const CHUNK_TERMS: usize = 1 << 16;
+        let short = |term: &WordInfo| term.len as usize <= MAX_QUAD_TERM_LEN;
+        let mut chars = Vec::new();
+
+        let mut counts = vec![0; <[char; 4]>::DENSE_LEN];
+        for term in terms.iter().filter(|term| short(term)) {
+            chars.clear();
+            chars.extend(term.lower.chars());
+            for_each_quad(&chars, |quad| {
+                if let Some(slot) = quad.dense_slot() { counts[slot] += 1; }
+            });
+        }
+        self.quads.reserve(&counts);
+
+        let mut slices = vec![Vec::new(); (<[char; 4]>::DENSE_LEN >> SLICE_BITS) + 1];
+        for (chunk_start, chunk) in (first..).step_by(CHUNK_TERMS).zip(terms.chunks(CHUNK_TERMS)) {
+            for (term_idx, term) in (chunk_start..).zip(chunk).filter(|(_, term)| short(term)) {
+                chars.clear();
+                chars.extend(term.lower.chars());
+                for_each_quad(&chars, |quad| match quad.dense_slot() {
+                    Some(slot) => slices[slot >> SLICE_BITS].push((slot, term_idx)),
+                    None => self.quads.push(quad, term_idx),
+                });
+            }
+            // Within a slice the pushes stay in term order, so every list stays sorted
+            for slice in &mut slices {
+                for (slot, term_idx) in slice.drain(..) {
+                    self.quads.push_dense(slot, term_idx);
+                }
+            }
+        }
+    }
+
+    fn add(&mut self, chars: &[char], term_idx: u32, quads: bool) {
+        for &c in chars {
+            self.chars.push(c, term_idx);
+        }
+        if chars.len() <= MAX_QUAD_TERM_LEN {
+            for_each_pair(chars, |pair| self.pairs.push(pair, term_idx));
+            if quads {
+                for_each_quad(chars, |quad| self.quads.push(quad, term_idx));
+            }
+        } else if chars.len() <= MAX_PAIR_TERM_LEN {
+            for_each_pair(chars, |pair| self.wide_pairs.push(pair, term_idx));
+        } else {
+            self.long.push(term_idx);
+        }
+    }
+
+    /// Renumbers term ids after compaction, dropping the ones mapped to `u32::MAX`
+    fn remap(&mut self, remap: &[u32]) {
+        // Surviving terms keep their relative order, so each list stays sorted
+        self.indexed = remap[..self.indexed as usize].iter().filter(|&&id| id != u32::MAX).count() as u32;
+        self.chars.remap(remap);
+        self.pairs.remap(remap);
+        self.quads.remap(remap);
+        self.wide_pairs.remap(remap);
+        self.long.retain(|&id| remap[id as usize] != u32::MAX);
+        for id in self.long.iter_mut() { *id = remap[*id as usize]; }
+    }
+
+    fn encode(&self, buf: &mut Vec<u8>, remap: &[u32]) {
+        self.chars.encode(buf, remap);
+        self.pairs.encode(buf, remap);
+        self.quads.encode(buf, remap);
+        self.wide_pairs.encode(buf, remap);
+        codec::put_u32s(buf, remapped(&self.long, remap));
+    }
+
+    fn decode(r: &mut Reader, term_count: u32) -> Option<Self> {
+        Some(Self {
+            indexed: term_count,
+            chars: Postings::decode(r, term_count)?,
+            pairs: Postings::decode(r, term_count)?,
+            quads: Postings::decode(r, term_count)?,
+            wide_pairs: Postings::decode(r, term_count)?,
+            long: read_ids(r, term_count)?,
+        })
+    }
+
+    /// Sorted term ids that may match the query: terms up to `MAX_QUAD_TERM_LEN` by their quad
+    /// (or, for short queries, pair) postings, longer ones by their own pair postings, and
+    /// every term too long for pairs
+    fn candidates(&self, search: &str) -> Cow<'_, [u32]> {
+        let query: Vec<char> = search.chars().collect();
+        match query[..] {
+            [] => return Cow::Borrowed(&[]),
+            [c] => return Cow::Borrowed(self.chars.get(c)),
+            _ => {}
+        }
+
+        let short = if query.len() >= 4 {
+            narrow(query.windows(4).map(|w| self.quads.get([w[0], w[1], w[2], w[3]])).collect())
+        } else {
+            narrow(query.windows(2).map(|w| self.pairs.get((w[0], w[1]))).collect())
+        };
+        let wide = narrow(query.windows(2).map(|w| self.wide_pairs.get((w[0], w[1]))).collect());
+        if wide.is_empty() && self.long.is_empty() {
+            return short;
+        }
+        // The three groups of terms are disjoint
+        let mut merged = short.into_owned();
+        merged.extend_from_slice(&wide);
+        merged.extend_from_slice(&self.long);
+        merged.sort_unstable();
+        Cow::Owned(merged)
+    }
+}
+
+impl TermIndex {
+    pub(super) fn len(&self) -> usize { self.terms.len() }
+
+    pub(super) fn get(&self, lower: &str) -> Option<u32> { self.map.get(&self.terms, lower) }
+
+    pub(super) fn items_mut(&mut self, term_idx: u32) -> &mut Vec<u32> {
+        &mut self.terms[term_idx as usize].items
+    }
+
+    /// Look up a term, registering it in the term table if it is new; the next search indexes it.
+    /// Returns the term index and whether the term was just created.
+    pub(super) fn intern(&mut self, lower: &str) -> (u32, bool) {
+        if let Some(idx) = self.get(lower) {
+            return (idx, false);
+        }
+        let term_idx = self.terms.len() as u32;
+        let len = lower.chars().count().min(255) as u8;
+        self.terms.push(WordInfo { lower: lower.into(), mask: char_mask(lower), len, items: Vec::new() });
+        self.map.insert(lower, term_idx);
+        (term_idx, true)
+    }
+
+    /// The postings, brought up to date with the term table
+    fn postings(&self) -> std::sync::MutexGuard<'_, TermPostings> {
+        // A panic part way through `catch_up` leaves nothing that a second call can't finish
+        let mut postings = self.postings.lock().unwrap_or_else(PoisonError::into_inner);
+        postings.catch_up(&self.terms);
+        postings
+    }
+
+    /// Drops terms whose posting lists are empty, renumbering the term table and indexes
+    pub(super) fn compact(&mut self) {
+        let mut remap = vec![u32::MAX; self.terms.len()];
+        let old = std::mem::take(&mut self.terms);
+        for (i, term) in old.into_iter().enumerate() {
+            if !term.items.is_empty() {
+                remap[i] = self.terms.len() as u32;
+                self.terms.push(term);
+            }
+        }
+        self.map.remap(&remap);
+        self.postings.get_mut().unwrap_or_else(PoisonError::into_inner).remap(&remap);
+    }
+
+    /// Writes the table with its postings for a snapshot, compacting away terms whose posting list is empty
+    pub(super) fn encode(&self, buf: &mut Vec<u8>) {
+        let mut remap = vec![u32::MAX; self.terms.len()];
+        let mut live = 0;
+        for (i, term) in self.terms.iter().enumerate() {
+            if !term.items.is_empty() {
+                remap[i] = live;
+                live += 1;
+            }
+        }
+        codec::put_u32(buf, live);
+        for term in self.terms.iter().filter(|t| !t.items.is_empty()) {
+            codec::put_str(buf, &term.lower);
+            codec::put_u64(buf, term.mask);
+            codec::put_u8(buf, term.len);
+            codec::put_u32s(buf, term.items.iter().copied());
+        }
+        self.postings().encode(buf, &remap);
+    }
+
+    /// Reads a table written by `encode`; every posting must satisfy `is_live`.
+    /// Records are split serially and built in parallel, leaving only the map inserts serial.
+    pub(super) fn decode(r: &mut Reader, is_live: impl Fn(u32) -> bool + Sync) -> Option<Self> {
+        let term_count = r.u32()?;
+        let mut records = Vec::with_capacity((term_count as usize).min(r.remaining()));
+        for _ in 0..term_count {
+            records.push((r.str()?, r.u64()?, r.u8()?, r.u32s_raw()?));
+        }
+        let built: Vec<WordInfo> = records
+            .into_par_iter()
+            .map(|(lower, mask, len, raw)| {
+                let items = codec::u32s(raw);
+                let valid = !items.is_empty() && items.windows(2).all(|w| w[0] < w[1]) && items.iter().all(|&i| is_live(i));
+                valid.then(|| WordInfo { lower: lower.into(), mask, len, items })
+            })
+            .collect::<Option<_>>()?;
+
+        let mut terms = Vec::with_capacity(built.len());
+        let mut map = TermMap::default();
+        map.ids.reserve(built.len());
+        for (term_idx, term) in (0..).zip(built) {
+            // A repeated term would mean the table was not written by `encode`
+            if map.get(&terms, &term.lower).is_some() { return None; }
+            map.insert(&term.lower, term_idx);
+            terms.push(term);
+        }
+        let postings = Mutex::new(TermPostings::decode(r, term_count)?);
+        Some(Self { terms, map, postings })
+    }
+
+    /// Sorted ids of the items with a term that matches `term` under `mode`
+    pub(super) fn matching(&self, term: &str, mode: MatchMode) -> Cow<'_, [u32]> {
+        // Fold case exactly like indexed terms were folded
+        let search = with_lower(term, str::to_owned);
+        if mode == MatchMode::Exact {
+            return match self.get(&search) {
+                Some(idx) => Cow::Borrowed(&self.terms[idx as usize].items),
+                None => Cow::Borrowed(&[]),
+            };
+        }
+        let postings = self.postings();
+        let candidates = postings.candidates(&search);
+
+        let search_mask = char_mask(&search);
+        let search_len = search.chars().count().min(255) as u8;
+        let prefix = mode == MatchMode::Prefix;
+
+        let verify = |&term_idx: &u32| {
+            let t = &self.terms[term_idx as usize];
+            // Check length first, then the character mask for quick rejection,
+            // and finally verify the match itself
+            (t.len >= search_len
+                && (search_mask & t.mask) == search_mask
+                && if prefix { t.lower.starts_with(search.as_str()) } else { is_subsequence(&search, &t.lower) })
+                .then_some(&t.items[..])
+        };
+        // Handing a few candidates to the thread pool costs more than checking them here
+        let mut matching: Vec<u32> = if candidates.len() < PARALLEL_CANDIDATES {
+            candidates.iter().filter_map(verify).flatten().copied().collect()
+        } else {
+            candidates.par_iter().filter_map(verify).flatten().copied().collect()
+        };
+        matching.sort_unstable();
+        matching.dedup();
+        Cow::Owned(matching)
+    }
+}
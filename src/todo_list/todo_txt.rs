@@ -0,0 +1,194 @@
+//! Conversion to and from the todo.txt line format: `x 2026-01-02 2026-01-01 call mom +family @phone`.
+//!
+//! `+project` becomes tag `project`, `@context` becomes tag `@context` and `due:YYYY-MM-DD` sets the
+//! due date. Priority `(A)` is high, `(B)` medium and `(C)` to `(Z)` low; letters past `C` and the
+//! creation and completion dates are kept in `Metadata`. Lines carry no index, so import numbers items in file order.
+//! todo.txt has no escaping, so export refuses items whose line would read back differently.
+use std::io::{self, BufRead, Write};
+
+use super::{is_tag_name, Metadata, Priority, TodoItem, TodoList};
+use crate::{Date, Description, Index, Tag};
+
+impl TodoItem {
+    /// Parses one todo.txt line. Whatever is not a marker, priority, date, project or context
+    /// stays in the description, so every line is accepted.
+    #[must_use] pub fn from_todo_txt(index: Index, line: &str) -> Self {
+        let mut tokens = line.split_whitespace().peekable();
+        let mut meta = Metadata::default();
+
+        let done = tokens.next_if_eq(&"x").is_some();
+        if !done {
+            meta.priority = tokens.next_if(|t| priority(t).is_some()).and_then(priority);
+        }
+        // A completed task lists its completion date first
+        let first = tokens.next_if(|t| is_date(t)).map(str::to_owned);
+        let second = first.as_ref().filter(|_| done).and_then(|_| tokens.next_if(|t| is_date(t))).map(str::to_owned);
+        if done {
+            meta.completed = first;
+            meta.created = second;
+        } else {
+            meta.created = first;
+        }
+
+        let mut words = Vec::new();
+        let mut tags = Vec::new();
+        let mut due = None;
+        for token in tokens {
+            let due_date = token.strip_prefix("due:").and_then(|d| d.parse().ok()).filter(|_| due.is_none());
+            // Completed tasks drop the `(A)` prefix, so their priority travels as `pri:A`
+            let pri = token.strip_prefix("pri:").and_then(letter).filter(|_| done && meta.priority.is_none());
+            if let Some(project) = token.strip_prefix('+').filter(|p| is_tag_name(p) && !p.starts_with('@')) {
+                tags.push(Tag::new(project));
+            } else if token.starts_with('@') && is_tag_name(token) {
+                tags.push(Tag::new(token));
+            } else if pri.is_some() {
+                meta.priority = pri;
+            } else if due_date.is_some() {
+                due = due_date;
+            } else {
+                words.push(token);
+            }
+        }
+
+        let priority = meta.priority.map(level);
+        // A level's own letter is implied, so only the rarer letters need remembering
+        if meta.priority == priority.map(letter_of) {
+            meta.priority = None;
+        }
+
+        let mut item = Self::new(index, Description::new(&words.join(" ")), tags);
+        item.done = done;
+        item.due = due;
+        item.priority = priority;
+        item.meta = (!meta.is_empty()).then(|| Box::new(meta));
+        item
+    }
+
+    /// Formats the item as a todo.txt line that `from_todo_txt` reads back unchanged. A description
+    /// starting like a marker, priority or date gets `today` as the date in front of it, which only
+    /// adds a creation (or completion) date.
+    /// # Errors
+    /// Returns a message if the item has no such line, e.g. because its description contains a
+    /// `+project` word or a double space.
+    pub fn to_todo_txt(&self, today: Date) -> Result<String, String> {
+        let line = self.todo_txt_line(today);
+        let back = Self::from_todo_txt(self.index, &line);
+        if (&back.description, &back.tags, back.done, back.due, back.priority) != (&self.description, &self.tags, self.done, self.due, self.priority) {
+            return Err(format!("item {} has no todo.txt form: it would read back as `{}`", self.index, back));
+        }
+        Ok(line)
+    }
+
+    fn todo_txt_line(&self, today: Date) -> String {
+        let meta = self.meta.as_deref();
+        // A remembered letter only counts while it still matches the item's priority
+        let letter = meta.and_then(|m| m.priority)
+            .filter(|l| Some(level(*l)) == self.priority)
+            .or_else(|| self.priority.map(letter_of));
+        let mut created = meta.and_then(|m| m.created.clone());
+        let mut completed = meta.and_then(|m| m.completed.clone());
+        let first = self.description.0.split_whitespace().next().unwrap_or_default();
+        if self.done {
+            // A done item's first date is its completion date, and a second one its creation date
+            if is_date(first) {
+                created.get_or_insert_with(|| today.to_string());
+            }
+            if created.is_some() {
+                completed.get_or_insert_with(|| today.to_string());
+            }
+        } else if first == "x" || priority(first).is_some() || is_date(first) {
+            created.get_or_insert_with(|| today.to_string());
+        }
+
+        let mut parts = Vec::new();
+        if self.done {
+            parts.push("x".to_owned());
+            parts.extend(completed);
+        } else if let Some(p) = letter {
+            parts.push(format!("({p})"));
+        }
+        parts.extend(created);
+        if !self.description.0.is_empty() {
+            parts.push(self.description.0.clone());
+        }
+        for tag in &self.tags {
+            parts.push(if tag.0.starts_with('@') { tag.0.clone() } else { format!("+{}", tag.0) });
+        }
+        if let Some(due) = self.due {
+            parts.push(format!("due:{due}"));
+        }
+        if let (true, Some(p)) = (self.done, letter) {
+            parts.push(format!("pri:{p}"));
+        }
+        parts.join(" ")
+    }
+}
+
+impl TodoList {
+    /// Writes every live item as a todo.txt line, in index order. Nothing is written unless
+    /// every item has a line that reads back unchanged.
+    /// # Errors
+    /// Returns an `InvalidData` error naming the items without a todo.txt form, or an I/O error if writing fails.
+    pub fn export_todo_txt<W: Write>(&self, mut out: W) -> io::Result<()> {
+        let today = self.today();
+        let (lines, errors): (Vec<_>, Vec<_>) = self.items().map(|item| item.to_todo_txt(today)).partition(Result::is_ok);
+        if !errors.is_empty() {
+            let errors: Vec<_> = errors.into_iter().filter_map(Result::err).collect();
+            return Err(io::Error::new(io::ErrorKind::InvalidData, errors.join("; ")));
+        }
+        for line in lines.into_iter().flatten() {
+            writeln!(out, "{line}")?;
+        }
+        out.flush()
+    }
+
+    /// Builds a fresh list from todo.txt lines, numbering items in file order; blank lines are skipped.
+    /// # Errors
+    /// Returns an I/O error if reading fails or the input is not UTF-8.
+    pub fn import_todo_txt<R: BufRead>(input: R) -> io::Result<Self> {
+        let mut tl = Self::new();
+        for line in input.lines() {
+            let line = line?;
+            if line.trim().is_empty() { continue; }
+            let item = TodoItem::from_todo_txt(tl.next_index(), &line);
+            let idx = tl.push(item.description, item.tags);
+            if item.done { tl.done_with_index(idx); }
+            if item.due.is_some() { tl.set_due(idx, item.due); }
+            if item.priority.is_some() { tl.set_priority(idx, item.priority); }
+            if let Some(meta) = item.meta { tl.set_meta(idx, *meta); }
+        }
+        Ok(tl)
+    }
+}
+
+/// The letter of a `(A)` priority
+fn priority(token: &str) -> Option<char> {
+    token.strip_prefix('(')?.strip_suffix(')').and_then(letter)
+}
+
+/// `A` is high, `B` medium and everything below low
+fn level(letter: char) -> Priority {
+    match letter {
+        'A' => Priority::High,
+        'B' => Priority::Medium,
+        _ => Priority::Low,
+    }
+}
+
+fn letter_of(priority: Priority) -> char {
+    match priority {
+        Priority::High => 'A',
+        Priority::Medium => 'B',
+        Priority::Low => 'C',
+    }
+}
+
+/// A single priority letter, `A` to `Z`
+fn letter(s: &str) -> Option<char> {
+    match s.as_bytes() {
+        [p @ b'A'..=b'Z'] => Some(char::from(*p)),
+        _ => None,
+    }
+}
+
+fn is_date(token: &str) -> bool { token.parse::<Date>().is_ok() }
@@ -0,0 +1,44 @@
+//! Helpers shared by the test files; each file uses only some of them.
+#![allow(dead_code)]
+
+use todo_swamp::{parser, runner, Date, FixedClock, Query, SearchParams, TodoList};
+
+/// The date that lists built by `build_dated_list` take as today
+pub fn today() -> Date { "2026-10-17".parse().unwrap() }
+
+/// Runs each command line against `tl`, discarding the output
+pub fn run_lines(tl: &mut TodoList, lines: &[&str]) {
+    for line in lines {
+        runner::run_line_buffered(line, tl, &mut std::io::sink(), b"\n").unwrap();
+    }
+}
+
+pub fn build_list(lines: &[&str]) -> TodoList {
+    let mut tl = TodoList::new();
+    run_lines(&mut tl, lines);
+    tl
+}
+
+/// A list whose clock says it is `today()`
+pub fn build_dated_list(lines: &[&str]) -> TodoList {
+    let mut tl = TodoList::new().with_clock(FixedClock(today()));
+    run_lines(&mut tl, lines);
+    tl
+}
+
+pub fn search_params(line: &str) -> SearchParams {
+    match parser::query(line) {
+        Ok(Query::Search(sp)) => sp,
+        other => panic!("`{}` did not parse as a search: {:?}", line, other),
+    }
+}
+
+/// Indices of the items a `search` line finds
+pub fn search(tl: &TodoList, line: &str) -> Vec<u64> {
+    tl.search(&search_params(line)).iter().map(|item| item.index.0).collect()
+}
+
+/// The items a `search` line finds, as they are printed
+pub fn search_lines(tl: &TodoList, line: &str) -> Vec<String> {
+    tl.search(&search_params(line)).iter().map(ToString::to_string).collect()
+}
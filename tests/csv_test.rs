@@ -0,0 +1,65 @@
+mod common;
+
+use todo_swamp::{CsvError, Index, TodoList};
+use common::{build_list, search_lines as search};
+
+#[test]
+fn test_csv_round_trip_keeps_indices_and_done() {
+    let tl = build_list(&[
+        "add \"buy bread, rye\" #groceries !high",
+        "add \"say \\\"hi\\\"\" #family #calls",
+        "add \"gone\"",
+        "add \"call parents\" due:2026-11-01",
+        "add \"  padded  \"",
+        "done 1",
+        "delete 2",
+    ]);
+    let mut csv = Vec::new();
+    tl.export_csv(&mut csv).unwrap();
+    assert_eq!(String::from_utf8(csv.clone()).unwrap(), "\
+index,description,tags,done,due,priority
+0,\"buy bread, rye\",groceries,false,,high
+1,\"say \"\"hi\"\"\",family calls,true,,
+3,call parents,,false,2026-11-01,
+4,  padded  ,,false,,
+");
+
+    let mut loaded = TodoList::import_csv(csv.as_slice()).unwrap();
+    for query in ["search --all", "search buy", "search #calls --all", "search --done", "search a", "search due:>2026-01-01", "search !high --by-priority --all"] {
+        assert_eq!(search(&tl, query), search(&loaded, query), "mismatch for `{query}`");
+    }
+    assert!(loaded.get(Index::new(2)).is_none());
+    assert_eq!(loaded.get(Index::new(4)).unwrap().description.0, "  padded  ");
+    assert_eq!(loaded.next_index(), Index::new(5));
+    assert_eq!(loaded.push(todo_swamp::Description::new("new"), vec![]), Index::new(5));
+}
+
+#[test]
+fn test_csv_import_from_spreadsheet() {
+    // Any column order, `#` on tags, loose booleans, padding and gaps are accepted
+    let csv = "done , index,tags,description\n yes ,5,#work later,Write report\n, 2 ,,plan week\n";
+    let tl = TodoList::import_csv(csv.as_bytes()).unwrap();
+    assert_eq!(search(&tl, "search --all"), vec!["2 \"plan week\"", "5 \"Write report\" #work #later"]);
+    assert_eq!(search(&tl, "search --done"), vec!["5 \"Write report\" #work #later"]);
+    assert_eq!(tl.next_index(), Index::new(6));
+}
+
+#[test]
+fn test_csv_import_reports_every_bad_row() {
+    let csv = "index,description,tags,done,due\n0,ok,,false,\nx,bad index,,false,\n1,bad tag,a.b,false,\n2,bad done,,maybe,\n0,duplicate,,true,\n3,short\n4,bad due,,false,2026-02-30\n";
+    let Err(CsvError::Rows(errors)) = TodoList::import_csv(csv.as_bytes()) else { panic!("import should fail") };
+    let messages: Vec<_> = errors.iter().map(ToString::to_string).collect();
+    assert_eq!(messages, [
+        "line 3: invalid index `x`",
+        "line 4: invalid tag `a.b`",
+        "line 5: invalid done value `maybe` (expected true or false)",
+        "line 6: duplicate index 0",
+        "line 7: expected 5 fields, found 2",
+        "line 8: invalid date `2026-02-30` (no such day)",
+    ]);
+
+    let Err(CsvError::Rows(errors)) = TodoList::import_csv("index,description\n0,x\n".as_bytes()) else { panic!("import should fail") };
+    assert_eq!(errors[0].to_string(), "line 1: missing column(s) `tags`, `done`");
+    let Err(CsvError::Rows(errors)) = TodoList::import_csv("index,description,tags,done\n99999999999,x,,\n".as_bytes()) else { panic!("import should fail") };
+    assert_eq!(errors[0].to_string(), "line 2: index 99999999999 leaves too many gaps");
+}
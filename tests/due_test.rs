@@ -0,0 +1,72 @@
+mod common;
+
+use todo_swamp::{runner, storage::Journal, Date, Index, TodoList};
+use common::{build_dated_list as build_list, search};
+
+fn date(s: &str) -> Date { s.parse().unwrap() }
+
+#[test]
+fn test_dates() {
+    assert_eq!(date("2024-02-29").to_string(), "2024-02-29");
+    assert!("2023-02-29".parse::<Date>().is_err());
+    assert!("1900-02-29".parse::<Date>().is_err());
+    assert!("2026-1-05".parse::<Date>().is_err());
+    assert!(date("2026-01-31") < date("2026-02-01"));
+    assert_eq!(Date::from_unix_days(0), date("1970-01-01"));
+    assert_eq!(Date::from_unix_days(20_743), date("2026-10-17"));
+    assert_eq!(Date::from_unix_days(-1), date("1969-12-31"));
+    assert_eq!(Date::from_packed(date("2026-10-17").to_packed()), Some(date("2026-10-17")));
+}
+
+#[test]
+fn test_due_filters() {
+    let mut tl = build_list(&[
+        "add \"pay rent\" #home due:2026-10-01",
+        "add \"file taxes\" due:2026-10-17",
+        "add \"book flights\" due:2026-11-01",
+        "add \"no deadline\"",
+        "add \"renew passport\" due:2026-09-30",
+        "done 4",
+    ]);
+    assert_eq!(search(&tl, "search due:<2026-10-17"), vec![0]);
+    assert_eq!(search(&tl, "search due:<=2026-10-17 --all"), vec![0, 1, 4]);
+    assert_eq!(search(&tl, "search due:2026-11-01"), vec![2]);
+    assert_eq!(search(&tl, "search due:>=today"), vec![1, 2]);
+    assert_eq!(search(&tl, "search due:>today"), vec![2]);
+    assert_eq!(search(&tl, "search due:today"), vec![1]);
+    // Done items are never overdue, even when the status filter lets them through
+    assert_eq!(search(&tl, "search overdue --all"), vec![0]);
+    assert_eq!(search(&tl, "search -overdue"), vec![1, 2, 3]);
+    assert_eq!(search(&tl, "search overdue OR flights"), vec![0, 2]);
+
+    // Changing or clearing a due date moves the item
+    tl.set_due(Index::new(2), Some(date("2026-10-02")));
+    tl.set_due(Index::new(0), None);
+    assert_eq!(search(&tl, "search overdue"), vec![2]);
+    tl.remove(Index::new(2));
+    assert_eq!(search(&tl, "search due:<today --all"), vec![4]);
+}
+
+#[test]
+fn test_due_dates_are_stored() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("todo.journal");
+    let mut tl = TodoList::new();
+    let mut journal = Journal::open(&path, &mut tl).unwrap();
+    let mut out = Vec::new();
+    for line in ["add \"pay rent\" #home due:2026-11-01", "add \"other\""] {
+        runner::run_line_journaled(line, &mut tl, Some(&mut journal), &mut out, b"\n").unwrap();
+    }
+    drop(journal);
+
+    let mut replayed = TodoList::new();
+    Journal::open(&path, &mut replayed).unwrap();
+    assert_eq!(replayed.get(Index::new(0)).unwrap().due, Some(date("2026-11-01")));
+    assert_eq!(search(&replayed, "search due:2026-11-01"), vec![0]);
+
+    let snapshot = dir.path().join("todo.snap");
+    replayed.save_snapshot(&snapshot).unwrap();
+    let loaded = TodoList::load_snapshot(&snapshot).unwrap();
+    assert_eq!(loaded.get(Index::new(0)).unwrap().to_string(), "0 \"pay rent\" #home due:2026-11-01");
+    assert_eq!(search(&loaded, "search due:>=2026-11-01"), vec![0]);
+}
@@ -3,7 +3,7 @@ use std::fs;
 use std::time::{Duration, Instant};
 
 fn get_binary() -> Command {
-    Command::cargo_bin("application").unwrap()
+    Command::cargo_bin("todo").unwrap()
 }
 
 fn get_binary_with_timeout(secs: u64) -> Command {
@@ -28,17 +28,18 @@ fn run_and_get_output(input: &str) -> String {
     String::from_utf8(output.stdout).unwrap()
 }
 
-/// Run a performance test with given parameters
-fn run_perf_test(name: &str, input: String, timeout_secs: u64, max_secs: f64) {
+/// Run a performance test with given parameters, returning its output
+fn run_perf_test(name: &str, input: String, timeout_secs: u64, max_secs: f64) -> String {
     let start = Instant::now();
     let output = get_binary_with_timeout(timeout_secs)
         .write_stdin(input)
         .output()
-        .expect(&format!("{} timed out after {} seconds", name, timeout_secs));
+        .unwrap_or_else(|_| panic!("{} timed out after {} seconds", name, timeout_secs));
     assert!(output.status.success(), "{} failed or timed out", name);
     let elapsed = start.elapsed().as_secs_f64();
     println!("⏱️  {}: {:.2}s", name, elapsed);
     assert!(elapsed < max_secs, "{} took {:.2}s, expected < {:.0}s", name, elapsed, max_secs);
+    String::from_utf8(output.stdout).unwrap()
 }
 
 #[test]
@@ -104,6 +105,82 @@ fn test_done_filtering() {
     assert!(lines[found_idx + 1].trim().starts_with("1"));
 }
 
+#[test]
+fn test_undone_reopens_item() {
+    let actual = run_and_get_output("6\nadd \"taskone\" #work\nadd \"tasktwo\" #work\ndone 0\nundone 0\nundone 1\nsearch #work\n");
+    assert_eq!(normalize_output(&actual), normalize_output(
+        "0\n1\ndone\nundone\nError: Index 1 is not done\n2 item(s) found\n0 \"taskone\" #work\n1 \"tasktwo\" #work"
+    ));
+}
+
+#[test]
+fn test_edit_updates_search_index() {
+    let actual = run_and_get_output("7\nadd \"buy milk\" #groceries\nadd \"call mom\" #family\nedit 0 \"buy bread\"\nedit 1 #work\nsearch milk\nsearch bread #groceries\nsearch #work\n");
+    assert_eq!(normalize_output(&actual), normalize_output(
+        "0\n1\nedited\nedited\n0 item(s) found\n1 item(s) found\n0 \"buy bread\" #groceries\n1 item(s) found\n1 \"call mom\" #work"
+    ));
+}
+
+#[test]
+fn test_delete_removes_item() {
+    let actual = run_and_get_output("7\nadd \"buy milk\" #groceries\nadd \"buy bread\" #groceries\ndelete 0\ndelete 0\ndone 0\nsearch buy\nadd \"buy eggs\"\n");
+    assert_eq!(normalize_output(&actual), normalize_output(
+        "0\n1\ndeleted\nError: Index 0 not found\nError: Index 0 not found\n1 item(s) found\n1 \"buy bread\" #groceries\n2"
+    ));
+}
+
+#[test]
+fn test_search_status_filter() {
+    let actual = run_and_get_output("6\nadd \"buy milk\" #groceries\nadd \"buy bread\" #groceries\ndone 0\nsearch buy --done\nsearch --all #groceries\nsearch --open\n");
+    assert_eq!(normalize_output(&actual), normalize_output(
+        "0\n1\ndone\n\
+         1 item(s) found\n0 \"buy milk\" #groceries\n\
+         2 item(s) found\n0 \"buy milk\" #groceries\n1 \"buy bread\" #groceries\n\
+         1 item(s) found\n1 \"buy bread\" #groceries"
+    ));
+}
+
+#[test]
+fn test_boolean_search() {
+    let actual = run_and_get_output("8\nadd \"buy milk\" #groceries\nadd \"buy bread\" #groceries #blocked\nadd \"fix bike\" #work\nadd \"write report\" #work #blocked\n\
+        search milk OR bread\nsearch #work -#blocked\nsearch buy NOT (milk OR report)\nsearch (milk\n");
+    assert_eq!(normalize_output(&actual), normalize_output(
+        "0\n1\n2\n3\n\
+         2 item(s) found\n0 \"buy milk\" #groceries\n1 \"buy bread\" #groceries #blocked\n\
+         1 item(s) found\n2 \"fix bike\" #work\n\
+         1 item(s) found\n1 \"buy bread\" #groceries #blocked\n\
+         Error: expected closing parenthesis at column 13, found end of input"
+    ));
+}
+
+#[test]
+fn test_unicode_descriptions_and_tags() {
+    let actual = run_and_get_output("7\nadd \"Café crème\" #Küche\nadd \"Купить молоко\" #дом\nadd \"خرید نان\" #خانه\nadd \"room 101 cleanup\" #v2\n\
+        search CAFÉ #küche\nsearch кпть #ДОМ\nsearch نن\n");
+    assert_eq!(normalize_output(&actual), normalize_output(
+        "0\n1\n2\n3\n\
+         1 item(s) found\n0 \"Café crème\" #Küche\n\
+         1 item(s) found\n1 \"Купить молоко\" #дом\n\
+         1 item(s) found\n2 \"خرید نان\" #خانه"
+    ));
+
+    // A decomposed `é` is a base letter plus a combining accent, still one word
+    let actual = run_and_get_output("2\nadd \"cafe\u{301} noir\" #cafe\u{301}\nsearch cafe\u{301} #cafe\u{301}\n");
+    assert_eq!(normalize_output(&actual), normalize_output("0\n1 item(s) found\n0 \"cafe\u{301} noir\" #cafe\u{301}"));
+}
+
+#[test]
+fn test_punctuation_and_escaped_quotes() {
+    let actual = run_and_get_output("6\nadd \"ship v2.1 by 5pm!\" #release\nadd \"say \\\"hi\\\" to C:\\\\temp\"\nadd \"bad \\n escape\"\nsearch 5pm v2\nsearch temp\nsearch 1 #release\n");
+    assert_eq!(normalize_output(&actual), normalize_output(
+        "0\n1\n\
+         Error: expected escape sequence (\\\" or \\\\) at column 11, found \"n escape\"\"\n\
+         1 item(s) found\n0 \"ship v2.1 by 5pm!\" #release\n\
+         1 item(s) found\n1 \"say \\\"hi\\\" to C:\\\\temp\"\n\
+         1 item(s) found\n0 \"ship v2.1 by 5pm!\" #release"
+    ));
+}
+
 #[test]
 fn test_subsequence_matching() {
     let actual = run_and_get_output("2\nadd \"bread\" #food\nsearch a\n");
@@ -149,6 +226,395 @@ fn test_output_format() {
     }
 }
 
+#[test]
+fn test_parse_errors_are_reported() {
+    let actual = run_and_get_output("4\nadd milk\nadd \"milk\" #groceries\nsearch #\nsearch milk\n");
+    assert_eq!(normalize_output(&actual), normalize_output(
+        "Error: expected description at column 5, found \"milk\"\n\
+         0\n\
+         Error: expected tag name at column 9, found end of input\n\
+         1 item(s) found\n\
+         0 \"milk\" #groceries"
+    ));
+}
+
+#[test]
+fn test_fail_fast_stops_at_first_error() {
+    let output = get_binary()
+        .arg("--fail-fast")
+        .write_stdin("3\nadd \"one\"\ndone x\nadd \"two\"\n")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let actual = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(normalize_output(&actual), normalize_output("0\nError: expected index at column 6, found \"x\""));
+}
+
+#[test]
+fn test_repl_runs_commands_without_count_header() {
+    let output = get_binary()
+        .arg("--repl")
+        .write_stdin("add \"buy milk\" #food\n\nsearch milk\ndone 5\n")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let actual = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(normalize_output(&actual), normalize_output(
+        "0\n1 item(s) found\n0 \"buy milk\" #food\nError: Index 5 not found"
+    ));
+
+    // `--batch` keeps the counted format
+    let output = get_binary().arg("--batch").write_stdin("1\nadd \"x\"\nadd \"y\"\n").output().unwrap();
+    assert_eq!(normalize_output(&String::from_utf8(output.stdout).unwrap()), normalize_output("0"));
+}
+
+#[test]
+fn test_batch_streams_results_before_input_ends() {
+    use std::io::{BufRead, BufReader, Write};
+    use std::process::{Command as StdCommand, Stdio};
+
+    let mut child = StdCommand::new(env!("CARGO_BIN_EXE_todo"))
+        .arg("--batch")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+
+    // The first result must arrive while stdin is still open
+    stdin.write_all(b"2\nadd \"first\"\n").unwrap();
+    stdin.flush().unwrap();
+    let mut line = String::new();
+    stdout.read_line(&mut line).unwrap();
+    assert_eq!(line, "0\n");
+
+    stdin.write_all(b"add \"second\"\n").unwrap();
+    drop(stdin);
+    line.clear();
+    stdout.read_line(&mut line).unwrap();
+    assert_eq!(line, "1\n");
+    assert!(child.wait().unwrap().success());
+}
+
+#[test]
+fn test_line_endings_follow_each_command() {
+    let actual = run_and_get_output("3\r\nadd \"a\"\r\nadd \"b\"\nsearch a");
+    assert_eq!(actual, "0\r\n1\n1 item(s) found\n0 \"a\"\n");
+}
+
+#[test]
+fn test_subcommands_share_data_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let data = dir.path().join("todo.journal");
+    let data = data.to_str().unwrap();
+    let run = |args: &[&str]| {
+        let output = get_binary().args(["--data-file", data]).args(args).output().unwrap();
+        (output.status.code(), String::from_utf8(output.stdout).unwrap())
+    };
+
+    assert_eq!(run(&["add", "buy milk", "#groceries"]), (Some(0), "0\n".into()));
+    assert_eq!(run(&["add", "say \"hi\"", "family"]), (Some(0), "1\n".into()));
+    assert_eq!(run(&["done", "0"]), (Some(0), "done\n".into()));
+    assert_eq!(run(&["done", "7"]), (Some(1), "Error: Index 7 not found\n".into()));
+    assert_eq!(run(&["search", "--all", "#groceries", "OR", "hi"]),
+        (Some(0), "2 item(s) found\n0 \"buy milk\" #groceries\n1 \"say \\\"hi\\\"\" #family\n".into()));
+    assert_eq!(run(&["--concise", "search"]), (Some(0), "1 item(s) found\n1\n".into()));
+    // Options belong before the command; after it they are part of the query
+    assert_eq!(run(&["search", "--concise"]).0, Some(1));
+
+    let script = dir.path().join("script.txt");
+    fs::write(&script, "add \"water plants\"\nsearch plants\n").unwrap();
+    assert_eq!(run(&["run", script.to_str().unwrap()]),
+        (Some(0), "2\n1 item(s) found\n2 \"water plants\"\n".into()));
+
+    assert_eq!(get_binary().arg("frob").output().unwrap().status.code(), Some(2));
+    assert!(String::from_utf8(get_binary().arg("--help").output().unwrap().stdout).unwrap().starts_with("Usage:"));
+}
+
+#[test]
+fn test_torn_data_file_is_reported() {
+    let dir = tempfile::tempdir().unwrap();
+    let data = dir.path().join("todo.journal");
+    let data = data.to_str().unwrap();
+    get_binary().args(["--data-file", data, "add", "first"]).assert().success();
+    get_binary().args(["--data-file", data, "add", "second"]).assert().success();
+    let len = fs::metadata(data).unwrap().len();
+    fs::OpenOptions::new().write(true).open(data).unwrap().set_len(len - 3).unwrap();
+
+    // A lookup only skips the torn record; the next writer cuts it off
+    let output = get_binary().args(["--data-file", data, "search"]).output().unwrap();
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "1 item(s) found\n0 \"first\"\n");
+    assert!(String::from_utf8(output.stderr).unwrap().starts_with("warning: skipped "));
+    assert_eq!(fs::metadata(data).unwrap().len(), len - 3);
+
+    let output = get_binary().args(["--data-file", data, "add", "third"]).output().unwrap();
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "1\n");
+    assert!(String::from_utf8(output.stderr).unwrap().starts_with("warning: dropped "));
+}
+
+#[test]
+fn test_data_file_is_checkpointed() {
+    let dir = tempfile::tempdir().unwrap();
+    let data = dir.path().join("todo.journal");
+    let data = data.to_str().unwrap();
+    let snapshot = format!("{data}.snap");
+
+    let input: String = std::iter::once("10000\n".to_string())
+        .chain((0..10_000).map(|i| format!("add \"task {i}\" #batch\n")))
+        .collect();
+    get_binary().args(["--data-file", data]).write_stdin(input).assert().success();
+    assert!(fs::metadata(&snapshot).is_ok());
+
+    // Startup takes the snapshot plus the records written after it
+    get_binary().args(["--data-file", data, "add", "one more"]).assert().stdout("10000\n");
+    get_binary().args(["--data-file", data, "--concise", "search", "task", "OR", "more"])
+        .assert().stdout(predicates::str::starts_with("10001 item(s) found\n"));
+    get_binary().args(["--data-file", data, "count", "task 9999"]).assert().stdout("1\n");
+}
+
+#[test]
+fn test_fail_fast_still_finishes_data_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let data = dir.path().join("todo.journal");
+    let data = data.to_str().unwrap();
+
+    // The records before the failed line are synced and checkpointed as usual
+    let input: String = std::iter::once("10001\n".to_string())
+        .chain((0..10_000).map(|i| format!("add \"task {i}\"\n")))
+        .chain(std::iter::once("done x\n".to_string()))
+        .collect();
+    get_binary().args(["--data-file", data, "--fail-fast"]).write_stdin(input).assert().code(1);
+    assert!(fs::metadata(format!("{data}.snap")).is_ok());
+    get_binary().args(["--data-file", data, "count", "task"]).assert().stdout("10000\n");
+}
+
+#[test]
+fn test_lookups_do_not_create_data_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let data = dir.path().join("typo.journal");
+    let data = data.to_str().unwrap();
+
+    for command in ["search", "count", "export"] {
+        let output = get_binary().args(["--data-file", data, command]).output().unwrap();
+        assert_eq!(output.status.code(), Some(2));
+        assert_eq!(String::from_utf8(output.stderr).unwrap(), format!("error: data file {data} does not exist\n"));
+    }
+    assert!(!std::path::Path::new(data).exists());
+
+    get_binary().args(["--data-file", data, "add", "first"]).assert().success();
+    get_binary().args(["--data-file", data, "count"]).assert().stdout("1\n");
+}
+
+#[test]
+fn test_lookups_run_next_to_a_writer() {
+    use std::io::{BufRead, BufReader, Write};
+    use std::process::Stdio;
+
+    let dir = tempfile::tempdir().unwrap();
+    let data = dir.path().join("todo.journal");
+    let data = data.to_str().unwrap();
+
+    let mut repl = std::process::Command::new(assert_cmd::cargo::cargo_bin("todo"))
+        .args(["--data-file", data, "--repl"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = repl.stdin.take().unwrap();
+    let mut stdout = BufReader::new(repl.stdout.take().unwrap());
+    writeln!(stdin, "add \"first\"").unwrap();
+    let mut line = String::new();
+    stdout.read_line(&mut line).unwrap();
+    assert_eq!(line, "0\n");
+
+    // Lookups share the data file with the session; a second writer is still refused
+    get_binary().args(["--data-file", data, "count"]).assert().success().stdout("1\n");
+    get_binary().args(["--data-file", data, "search", "first"]).assert().success().stdout("1 item(s) found\n0 \"first\"\n");
+    get_binary().args(["--data-file", data, "add", "second"]).assert().code(2)
+        .stderr(predicates::str::contains("journal is in use by another process"));
+
+    drop(stdin);
+    assert!(repl.wait().unwrap().success());
+}
+
+#[test]
+fn test_csv_import_export_commands() {
+    let dir = tempfile::tempdir().unwrap();
+    let data = dir.path().join("todo.journal");
+    let data = data.to_str().unwrap();
+    let csv = dir.path().join("items.csv");
+    fs::write(&csv, "index,description,tags,done\n1,buy milk,groceries,true\n3,call mom,,false\n").unwrap();
+    let csv = csv.to_str().unwrap();
+    let run = |args: &[&str]| {
+        let output = get_binary().args(["--data-file", data]).args(args).output().unwrap();
+        (output.status.code(), String::from_utf8(output.stdout).unwrap())
+    };
+
+    assert_eq!(run(&["import", csv]), (Some(0), "2 item(s) imported\n".into()));
+    assert_eq!(run(&["search", "--all"]),
+        (Some(0), "2 item(s) found\n1 \"buy milk\" #groceries\n3 \"call mom\"\n".into()));
+    assert_eq!(run(&["add", "new"]), (Some(0), "4\n".into()));
+    assert_eq!(run(&["export"]),
+        (Some(0), "index,description,tags,done,due,priority\n1,buy milk,groceries,true,,\n3,call mom,,false,,\n4,new,,false,,\n".into()));
+    // Imported indices would clash with the existing ones
+    assert_eq!(run(&["import", csv]).0, Some(1));
+
+    let bad = dir.path().join("bad.csv");
+    fs::write(&bad, "index,description,tags,done\n0,x,,nope\n").unwrap();
+    let other = dir.path().join("other.journal");
+    let output = get_binary().args(["--data-file", other.to_str().unwrap(), "import", bad.to_str().unwrap()]).output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8(output.stderr).unwrap().contains("line 2: invalid done value `nope`"));
+
+    // Without a data file the items would have nowhere to go
+    let output = get_binary().args(["import", csv]).output().unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(String::from_utf8(output.stderr).unwrap(), "error: import needs --data-file to store the items in\n");
+    let output = get_binary().arg("export").output().unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(String::from_utf8(output.stderr).unwrap(), "error: export needs --data-file to read the items from\n");
+}
+
+#[test]
+fn test_todo_txt_import_export_commands() {
+    let dir = tempfile::tempdir().unwrap();
+    let data = dir.path().join("todo.journal");
+    let data = data.to_str().unwrap();
+    let todo = dir.path().join("todo.txt");
+    fs::write(&todo, "(B) 2026-01-05 call mom +family @phone\nx 2026-01-09 pay rent +home\n").unwrap();
+    let run = |args: &[&str]| {
+        let output = get_binary().args(["--data-file", data]).args(args).output().unwrap();
+        (output.status.code(), String::from_utf8(output.stdout).unwrap())
+    };
+
+    assert_eq!(run(&["import", todo.to_str().unwrap()]), (Some(0), "2 item(s) imported\n".into()));
+    assert_eq!(run(&["search", "#@ph"]), (Some(0), "1 item(s) found\n0 \"call mom\" #family #@phone !medium\n".into()));
+    assert_eq!(run(&["add", "water plants", "@home"]), (Some(0), "2\n".into()));
+    assert_eq!(run(&["--file-format", "todo.txt", "export"]), (Some(0),
+        "(B) 2026-01-05 call mom +family @phone\nx 2026-01-09 pay rent +home\nwater plants @home\n".into()));
+    assert_eq!(run(&["--file-format", "xml", "export"]).0, Some(2));
+
+    // An item without a todo.txt form fails the export and leaves the file alone
+    assert_eq!(run(&["add", "grade +1"]), (Some(0), "3\n".into()));
+    assert_eq!(run(&["export", todo.to_str().unwrap()]).0, Some(1));
+    assert_eq!(fs::read_to_string(&todo).unwrap(), "(B) 2026-01-05 call mom +family @phone\nx 2026-01-09 pay rent +home\n");
+
+    // Without a data file there is nothing to export, and the file is left alone
+    let output = get_binary().args(["--file-format", "todo.txt", "export", todo.to_str().unwrap()]).output().unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(fs::read_to_string(&todo).unwrap(), "(B) 2026-01-05 call mom +family @phone\nx 2026-01-09 pay rent +home\n");
+}
+
+#[test]
+fn test_search_finds_punctuated_descriptions() {
+    let actual = run_and_get_output("3\nadd \"ship v2.1\"\nadd \"ship v2\"\nsearch v2.1\n");
+    assert_eq!(actual, "0\n1\n1 item(s) found\n0 \"ship v2.1\"\n");
+}
+
+#[test]
+fn test_due_dates() {
+    // Far-off dates keep this independent of the real clock
+    let actual = run_and_get_output("5\nadd \"pay rent\" #home due:2000-01-01\nadd \"book flights\" due:2999-01-01\nadd \"x\" due:2026-13-01\nsearch overdue\nsearch due:>today\n");
+    assert_eq!(actual, "0\n1\nError: expected date (YYYY-MM-DD) at column 13, found \"2026-13-01\"\n\
+1 item(s) found\n0 \"pay rent\" #home due:2000-01-01\n1 item(s) found\n1 \"book flights\" due:2999-01-01\n");
+
+    let output = get_binary().args(["--format", "json"]).write_stdin("2\n{\"op\":\"add\",\"description\":\"a\",\"due\":\"2000-01-01\"}\nsearch overdue\n").output().unwrap();
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "{\"type\":\"added\",\"index\":0}\n\
+{\"type\":\"found\",\"count\":1,\"total\":1,\"items\":[{\"index\":0,\"description\":\"a\",\"tags\":[],\"done\":false,\"due\":\"2000-01-01\"}]}\n");
+}
+
+#[test]
+fn test_json_lines_output() {
+    let output = get_binary()
+        .args(["--format", "json"])
+        .write_stdin("6\nadd \"say \\\"hi\\\"\" #a #b\nadd \"other\"\ndone 0\nundone 1\nsearch --all hi\nadd x\n")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "\
+{\"type\":\"added\",\"index\":0}
+{\"type\":\"added\",\"index\":1}
+{\"type\":\"done\",\"index\":0}
+{\"type\":\"error\",\"code\":\"not_done\",\"message\":\"Index 1 is not done\"}
+{\"type\":\"found\",\"count\":1,\"total\":1,\"items\":[{\"index\":0,\"description\":\"say \\\"hi\\\"\",\"tags\":[\"a\",\"b\"],\"done\":true}]}
+{\"type\":\"error\",\"code\":\"parse_error\",\"message\":\"expected description at column 5, found \\\"x\\\"\"}
+");
+
+    let output = get_binary().args(["--format", "json", "--concise", "search"]).output().unwrap();
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "{\"type\":\"found\",\"count\":0,\"total\":0,\"items\":[]}\n");
+    assert_eq!(get_binary().args(["--format", "xml"]).output().unwrap().status.code(), Some(2));
+}
+
+#[test]
+fn test_relevance_scores_in_json() {
+    let output = get_binary()
+        .args(["--format", "json", "--concise", "--scores"])
+        .write_stdin("4\nadd \"birthday card\"\nadd \"bread\" !low\nsearch bd sort:relevance\nsearch bd\n")
+        .output()
+        .unwrap();
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "\
+{\"type\":\"added\",\"index\":0}
+{\"type\":\"added\",\"index\":1}
+{\"type\":\"found\",\"count\":2,\"total\":2,\"items\":[{\"index\":1,\"priority\":\"low\",\"score\":41},{\"index\":0,\"score\":38}]}
+{\"type\":\"found\",\"count\":2,\"total\":2,\"items\":[{\"index\":0,\"score\":38},{\"index\":1,\"priority\":\"low\",\"score\":41}]}
+");
+    // Text output is unchanged
+    let output = get_binary().args(["--concise", "--scores", "search"]).output().unwrap();
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "0 item(s) found\n");
+}
+
+#[test]
+fn test_count_command() {
+    let actual = run_and_get_output("5\nadd \"buy milk\" #shop\nadd \"buy bread\" #shop\ndone 1\ncount #shop\ncount #shop --all\n");
+    assert_eq!(actual, "0\n1\ndone\n1\n2\n");
+
+    let output = get_binary()
+        .args(["--format", "json", "--limit", "1"])
+        .write_stdin("3\nadd \"a\"\nadd \"b\"\n{\"op\":\"count\"}\n")
+        .output()
+        .unwrap();
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "{\"type\":\"added\",\"index\":0}\n{\"type\":\"added\",\"index\":1}\n{\"type\":\"counted\",\"count\":2}\n");
+}
+
+#[test]
+fn test_json_commands_mix_with_text() {
+    let actual = run_and_get_output("4\n{\"op\":\"add\",\"description\":\"say \\\"hi\\\"\",\"tags\":[\"greeting\"]}\nadd \"other\" #greeting\n{\"op\":\"done\",\"index\":1}\n{\"op\":\"search\",\"query\":\"#greeting --all\"}\n");
+    assert_eq!(normalize_output(&actual), normalize_output(
+        "0\n1\ndone\n2 item(s) found\n0 \"say \\\"hi\\\"\" #greeting\n1 \"other\" #greeting"
+    ));
+}
+
+#[test]
+fn test_large_header_keeps_subsequence_semantics() {
+    // The declared command count no longer switches matching strategy or truncates results
+    let mut input = String::from("10500\nadd \"buy bread\" #groceries\n");
+    for _ in 0..150 {
+        input.push_str("add \"birthday card\" #family\n");
+    }
+    input.push_str("search bd\n");
+    let actual = run_and_get_output(&input);
+    assert!(actual.contains("151 item(s) found"));
+}
+
+#[test]
+fn test_match_mode_and_limit_flags() {
+    let input = "4\nadd \"buy bread\" #groceries\nadd \"brown rice\" #groceries\nsearch br\nsearch bd\n";
+    let run = |args: &[&str]| {
+        let output = get_binary().args(args).write_stdin(input).output().unwrap();
+        assert!(output.status.success());
+        normalize_output(&String::from_utf8(output.stdout).unwrap())
+    };
+    assert_eq!(run(&["--match-mode", "prefix"]), normalize_output(
+        "0\n1\n2 item(s) found\n0 \"buy bread\" #groceries\n1 \"brown rice\" #groceries\n0 item(s) found"
+    ));
+    assert_eq!(run(&["--match-mode", "exact"]), normalize_output("0\n1\n0 item(s) found\n0 item(s) found"));
+    assert_eq!(run(&["--limit", "1"]), normalize_output(
+        "0\n1\n1 of 2 item(s) found\n0 \"buy bread\" #groceries\n1 item(s) found\n0 \"buy bread\" #groceries"
+    ));
+    assert!(!get_binary().args(["--match-mode", "fuzzy"]).write_stdin(input).output().unwrap().status.success());
+}
+
 #[test]
 fn test_performance_medium() {
     const WORDS: [&str; 10] = ["alpha", "beta", "gamma", "delta", "epsilon", "zeta", "eta", "theta", "iota", "kappa"];
@@ -184,11 +650,12 @@ fn test_performance_large() {
     run_perf_test("test_performance_large", input, 10, 10.0);
 }
 
-// Helper to generate random-ish words based on index
+/// Six-letter word for `i`; the multiplier scatters consecutive indices over all letters
+/// (it is coprime with 26^6, so distinct indices below 26^6 give distinct words)
 fn generate_word(i: usize) -> String {
     const CHARS: &[u8] = b"abcdefghijklmnopqrstuvwxyz";
     let mut word = String::with_capacity(6);
-    let mut n = i;
+    let mut n = i.wrapping_mul(2_654_435_761) % 308_915_776;
     for _ in 0..6 {
         word.push(CHARS[n % 26] as char);
         n /= 26;
@@ -196,285 +663,93 @@ fn generate_word(i: usize) -> String {
     word
 }
 
-#[test]
-fn test_performance_100k() {
-    let n = 100_000;
+/// `n` commands: `n / 2` adds of three words and two tags (out of `tags`), then searches
+/// for subsequences of the added words, alone, with a tag or with a second word.
+/// Every search matches at least the item its terms were taken from.
+fn perf_input(n: usize, tags: usize) -> String {
     let adds = n / 2;
-    let searches = n / 2;
-    
     let mut input = format!("{}\n", n);
     for i in 0..adds {
-        let w1 = generate_word(i);
-        let w2 = generate_word(i * 7);
-        let tag = generate_word(i % 1000);
-        input.push_str(&format!("add {} {} #{}\n", w1, w2, tag));
+        input.push_str(&format!(
+            "add \"{} {} {}\" #{} #{}\n",
+            generate_word(i), generate_word(i * 7), generate_word(i * 13), generate_word(i % tags), generate_word(i * 3 % tags)
+        ));
     }
-    for i in 0..searches {
-        let term = &generate_word(i % 1000)[..3];
-        input.push_str(&format!("search {}\n", term));
+    for j in 0..n - adds {
+        let i = j * 7919 % adds;
+        // Drop one letter, so the term is a subsequence rather than a prefix
+        let drop = 1 + j % 4;
+        let mut term = generate_word(i * 7);
+        term.remove(drop);
+        match j % 3 {
+            0 => input.push_str(&format!("search {}\n", term)),
+            1 => input.push_str(&format!("search {} #{}\n", term, generate_word(i % tags))),
+            _ => {
+                let mut second = generate_word(i * 13);
+                second.remove(5 - drop % 2);
+                input.push_str(&format!("search {} {}\n", term, second));
+            }
+        }
     }
-    
-    let start = Instant::now();
-    let output = get_binary_with_timeout(10)
-        .write_stdin(input)
-        .output()
-        .expect("Command timed out after 10 seconds");
-    assert!(output.status.success(), "Performance test 100K failed or timed out");
-    let elapsed = start.elapsed().as_secs_f64();
-    println!("⏱️  test_performance_100k: {:.2}s", elapsed);
-    assert!(elapsed < 10.0, "100K commands took {:.2}s, expected < 10s", elapsed);
+    input
+}
+
+/// `run_perf_test` on `perf_input`, checking that every add parsed and every search found its item
+fn run_generated_perf_test(name: &str, n: usize, tags: usize, timeout_secs: u64, max_secs: f64) {
+    let output = run_perf_test(name, perf_input(n, tags), timeout_secs, max_secs);
+    assert!(!output.lines().any(|l| l.starts_with("Error")), "{} input was rejected", name);
+    assert!(!output.lines().any(|l| l == "0 item(s) found"), "{} search missed its item", name);
+}
+
+#[test]
+fn test_performance_100k() {
+    run_generated_perf_test("test_performance_100k", 100_000, 1000, 10, 10.0);
 }
 
 #[test]
 fn test_performance_1m() {
-    let n = 1_000_000;
-    let adds = n / 2;
-    let searches = n / 2;
-    
-    let mut input = format!("{}\n", n);
-    for i in 0..adds {
-        let w1 = generate_word(i);
-        let w2 = generate_word(i * 7);
-        let tag = generate_word(i % 5000);
-        input.push_str(&format!("add {} {} #{}\n", w1, w2, tag));
-    }
-    for i in 0..searches {
-        let term = &generate_word(i % 5000)[..3];
-        input.push_str(&format!("search {}\n", term));
-    }
-    
-    let start = Instant::now();
-    let output = get_binary_with_timeout(10)
-        .write_stdin(input)
-        .output()
-        .expect("Command timed out after 10 seconds");
-    assert!(output.status.success(), "Performance test 1M failed or timed out");
-    let elapsed = start.elapsed().as_secs_f64();
-    println!("⏱️  test_performance_1m: {:.2}s", elapsed);
-    assert!(elapsed < 10.0, "1M commands took {:.2}s, expected < 10s", elapsed);
+    run_generated_perf_test("test_performance_1m", 1_000_000, 5000, 10, 10.0);
 }
 
 #[test]
 fn test_performance_5m() {
-    let n = 5_000_000;
-    let adds = n / 2;
-    let searches = n / 2;
-    
-    let mut input = format!("{}\n", n);
-    for i in 0..adds {
-        let w1 = generate_word(i);
-        let w2 = generate_word(i * 7);
-        let w3 = generate_word(i * 13);
-        let tag1 = generate_word(i % 10000);
-        let tag2 = generate_word((i * 3) % 10000);
-        input.push_str(&format!("add {} {} {} #{} #{}\n", w1, w2, w3, tag1, tag2));
-    }
-    for i in 0..searches {
-        let term = &generate_word(i % 10000)[..3];
-        input.push_str(&format!("search {}\n", term));
-    }
-    
-    let start = Instant::now();
-    let output = get_binary_with_timeout(10)
-        .write_stdin(input)
-        .output()
-        .expect("Command timed out after 10 seconds");
-    assert!(output.status.success(), "Performance test 5M failed or timed out");
-    let elapsed = start.elapsed().as_secs_f64();
-    println!("⏱️  test_performance_5m: {:.2}s", elapsed);
-    assert!(elapsed < 10.0, "5M commands took {:.2}s, expected < 10s", elapsed);
+    run_generated_perf_test("test_performance_5m", 5_000_000, 10000, 10, 10.0);
 }
 
 #[test]
 #[ignore] // Heavy benchmark - run with: cargo test --release -- --ignored
 fn test_performance_10m() {
-    let n = 10_000_000;
-    let adds = n / 2;
-    let searches = n / 2;
-    
-    let mut input = format!("{}\n", n);
-    for i in 0..adds {
-        let w1 = generate_word(i);
-        let w2 = generate_word(i * 7);
-        let w3 = generate_word(i * 13);
-        let tag1 = generate_word(i % 20000);
-        let tag2 = generate_word((i * 3) % 20000);
-        input.push_str(&format!("add {} {} {} #{} #{}\n", w1, w2, w3, tag1, tag2));
-    }
-    for i in 0..searches {
-        let term = &generate_word(i % 20000)[..3];
-        input.push_str(&format!("search {}\n", term));
-    }
-    
-    let start = Instant::now();
-    let output = get_binary_with_timeout(10)
-        .write_stdin(input)
-        .output()
-        .expect("Command timed out after 10 seconds");
-    assert!(output.status.success(), "Performance test 10M failed or timed out");
-    let elapsed = start.elapsed().as_secs_f64();
-    println!("⏱️  test_performance_10m: {:.2}s", elapsed);
-    assert!(elapsed < 10.0, "10M commands took {:.2}s, expected < 10s", elapsed);
+    run_generated_perf_test("test_performance_10m", 10_000_000, 20000, 10, 10.0);
 }
 
 #[test]
 #[ignore] // Heavy benchmark - run with: cargo test --release -- --ignored
 fn test_performance_15m() {
-    let n = 15_000_000;
-    let adds = n / 2;
-    let searches = n / 2;
-    
-    let mut input = format!("{}\n", n);
-    for i in 0..adds {
-        let w1 = generate_word(i);
-        let w2 = generate_word(i * 7);
-        let w3 = generate_word(i * 13);
-        let tag1 = generate_word(i % 30000);
-        let tag2 = generate_word((i * 3) % 30000);
-        input.push_str(&format!("add {} {} {} #{} #{}\n", w1, w2, w3, tag1, tag2));
-    }
-    for i in 0..searches {
-        let term = &generate_word(i % 30000)[..3];
-        input.push_str(&format!("search {}\n", term));
-    }
-    
-    let start = Instant::now();
-    let output = get_binary_with_timeout(10)
-        .write_stdin(input)
-        .output()
-        .expect("Command timed out after 10 seconds");
-    assert!(output.status.success(), "Performance test 15M failed or timed out");
-    let elapsed = start.elapsed().as_secs_f64();
-    println!("⏱️  test_performance_15m: {:.2}s", elapsed);
-    assert!(elapsed < 10.0, "15M commands took {:.2}s, expected < 10s", elapsed);
+    run_generated_perf_test("test_performance_15m", 15_000_000, 30000, 10, 10.0);
 }
 
 #[test]
 #[ignore] // Heavy benchmark - run with: cargo test --release -- --ignored
 fn test_performance_20m() {
-    let n = 20_000_000;
-    let adds = n / 2;
-    let searches = n / 2;
-    
-    let mut input = format!("{}\n", n);
-    for i in 0..adds {
-        let w1 = generate_word(i);
-        let w2 = generate_word(i * 7);
-        let w3 = generate_word(i * 13);
-        let tag1 = generate_word(i % 50000);
-        let tag2 = generate_word((i * 3) % 50000);
-        input.push_str(&format!("add {} {} {} #{} #{}\n", w1, w2, w3, tag1, tag2));
-    }
-    for i in 0..searches {
-        let term = &generate_word(i % 50000)[..3];
-        input.push_str(&format!("search {}\n", term));
-    }
-    
-    let start = Instant::now();
-    let output = get_binary_with_timeout(15)
-        .write_stdin(input)
-        .output()
-        .expect("Command timed out after 15 seconds");
-    assert!(output.status.success(), "Performance test 20M failed or timed out");
-    let elapsed = start.elapsed().as_secs_f64();
-    println!("⏱️  test_performance_20m: {:.2}s", elapsed);
-    assert!(elapsed < 15.0, "20M commands took {:.2}s, expected < 15s", elapsed);
+    run_generated_perf_test("test_performance_20m", 20_000_000, 50000, 15, 15.0);
 }
 
 #[test]
 #[ignore] // Heavy benchmark - run with: cargo test --release -- --ignored
 fn test_performance_25m() {
-    let n = 25_000_000;
-    let adds = n / 2;
-    let searches = n / 2;
-    
-    let mut input = format!("{}\n", n);
-    for i in 0..adds {
-        let w1 = generate_word(i);
-        let w2 = generate_word(i * 7);
-        let w3 = generate_word(i * 13);
-        let tag1 = generate_word(i % 60000);
-        let tag2 = generate_word((i * 3) % 60000);
-        input.push_str(&format!("add {} {} {} #{} #{}\n", w1, w2, w3, tag1, tag2));
-    }
-    for i in 0..searches {
-        let term = &generate_word(i % 60000)[..3];
-        input.push_str(&format!("search {}\n", term));
-    }
-    
-    let start = Instant::now();
-    let output = get_binary_with_timeout(15)
-        .write_stdin(input)
-        .output()
-        .expect("Command timed out after 15 seconds");
-    assert!(output.status.success(), "Performance test 25M failed or timed out");
-    let elapsed = start.elapsed().as_secs_f64();
-    println!("⏱️  test_performance_25m: {:.2}s", elapsed);
-    assert!(elapsed < 15.0, "25M commands took {:.2}s, expected < 15s", elapsed);
+    run_generated_perf_test("test_performance_25m", 25_000_000, 60000, 15, 15.0);
 }
 
 #[test]
 #[ignore] // Heavy benchmark - run with: cargo test --release -- --ignored
 fn test_performance_30m() {
-    let n = 30_000_000;
-    let adds = n / 2;
-    let searches = n / 2;
-    
-    let mut input = format!("{}\n", n);
-    for i in 0..adds {
-        let w1 = generate_word(i);
-        let w2 = generate_word(i * 7);
-        let w3 = generate_word(i * 13);
-        let tag1 = generate_word(i % 70000);
-        let tag2 = generate_word((i * 3) % 70000);
-        input.push_str(&format!("add {} {} {} #{} #{}\n", w1, w2, w3, tag1, tag2));
-    }
-    for i in 0..searches {
-        let term = &generate_word(i % 70000)[..3];
-        input.push_str(&format!("search {}\n", term));
-    }
-    
-    let start = Instant::now();
-    let output = get_binary_with_timeout(15)
-        .write_stdin(input)
-        .output()
-        .expect("Command timed out after 15 seconds");
-    assert!(output.status.success(), "Performance test 30M failed or timed out");
-    let elapsed = start.elapsed().as_secs_f64();
-    println!("⏱️  test_performance_30m: {:.2}s", elapsed);
-    assert!(elapsed < 15.0, "30M commands took {:.2}s, expected < 15s", elapsed);
+    run_generated_perf_test("test_performance_30m", 30_000_000, 70000, 15, 15.0);
 }
 
 #[test]
 #[ignore] // Heavy benchmark - run with: cargo test --release -- --ignored
 fn test_performance_35m() {
-    let n = 35_000_000;
-    let adds = n / 2;
-    let searches = n / 2;
-    
-    let mut input = format!("{}\n", n);
-    for i in 0..adds {
-        let w1 = generate_word(i);
-        let w2 = generate_word(i * 7);
-        let w3 = generate_word(i * 13);
-        let tag1 = generate_word(i % 80000);
-        let tag2 = generate_word((i * 3) % 80000);
-        input.push_str(&format!("add {} {} {} #{} #{}\n", w1, w2, w3, tag1, tag2));
-    }
-    for i in 0..searches {
-        let term = &generate_word(i % 80000)[..3];
-        input.push_str(&format!("search {}\n", term));
-    }
-    
-    let start = Instant::now();
-    let output = get_binary_with_timeout(15)
-        .write_stdin(input)
-        .output()
-        .expect("Command timed out after 15 seconds");
-    assert!(output.status.success(), "Performance test 35M failed or timed out");
-    let elapsed = start.elapsed().as_secs_f64();
-    println!("⏱️  test_performance_35m: {:.2}s", elapsed);
-    assert!(elapsed < 15.0, "35M commands took {:.2}s, expected < 15s", elapsed);
+    run_generated_perf_test("test_performance_35m", 35_000_000, 80000, 15, 15.0);
 }
 
 #[test]
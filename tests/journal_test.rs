@@ -0,0 +1,219 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use todo_swamp::{parser, runner, storage::Journal, SearchParams, TodoList};
+
+fn run_lines(journal: &mut Journal, tl: &mut TodoList, lines: &[&str]) -> String {
+    let mut out = Vec::new();
+    for line in lines {
+        let _ = runner::run_line_journaled(line, tl, Some(journal), &mut out, b"\n");
+    }
+    String::from_utf8(out).unwrap()
+}
+
+fn open_indices(tl: &TodoList) -> Vec<u64> {
+    tl.search(&SearchParams::default()).iter().map(|item| item.index.0).collect()
+}
+
+#[test]
+fn test_journal_replays_adds_and_dones() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("todo.journal");
+
+    let mut tl = TodoList::new();
+    let mut journal = Journal::open(&path, &mut tl).unwrap();
+    let out = run_lines(&mut journal, &mut tl, &[
+        "add \"buy bread\" #groceries",
+        "add \"buy milk\" #groceries",
+        "add \"call parents\" #relatives",
+        "done 1",
+        "search buy",
+    ]);
+    assert_eq!(out, "0\n1\n2\ndone\n1 item(s) found\n0 \"buy bread\" #groceries\n");
+    drop(journal);
+
+    let mut tl = TodoList::new();
+    let mut journal = Journal::open(&path, &mut tl).unwrap();
+    assert_eq!(journal.discarded_bytes(), 0);
+    assert_eq!(open_indices(&tl), vec![0, 2]);
+
+    // Indices continue where the previous session stopped
+    let out = run_lines(&mut journal, &mut tl, &["add \"water plants\" #home", "search #groceries"]);
+    assert_eq!(out, "3\n1 item(s) found\n0 \"buy bread\" #groceries\n");
+}
+
+#[test]
+fn test_journal_skips_truncated_tail() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("todo.journal");
+
+    let mut tl = TodoList::new();
+    let mut journal = Journal::open(&path, &mut tl).unwrap();
+    run_lines(&mut journal, &mut tl, &["add \"first\" #a", "add \"second\" #b"]);
+    drop(journal);
+
+    // Simulate a crash halfway through writing the second record
+    let full_len = fs::metadata(&path).unwrap().len();
+    OpenOptions::new().write(true).open(&path).unwrap().set_len(full_len - 5).unwrap();
+
+    let mut tl = TodoList::new();
+    let mut journal = Journal::open(&path, &mut tl).unwrap();
+    assert!(journal.discarded_bytes() > 0);
+    assert_eq!(open_indices(&tl), vec![0]);
+
+    // The torn record was cut off, so new appends replay cleanly
+    run_lines(&mut journal, &mut tl, &["add \"third\" #c"]);
+    drop(journal);
+    let mut tl = TodoList::new();
+    let journal = Journal::open(&path, &mut tl).unwrap();
+    assert_eq!(journal.discarded_bytes(), 0);
+    assert_eq!(open_indices(&tl), vec![0, 1]);
+}
+
+#[test]
+fn test_journal_rejects_foreign_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("notes.txt");
+    fs::File::create(&path).unwrap().write_all(b"just some text file").unwrap();
+
+    let mut tl = TodoList::new();
+    assert!(Journal::open(&path, &mut tl).is_err());
+}
+
+#[test]
+fn test_journal_keeps_short_foreign_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("short.txt");
+    fs::write(&path, b"hi\n").unwrap();
+
+    let mut tl = TodoList::new();
+    let err = Journal::open(&path, &mut tl).err().unwrap();
+    assert_eq!(err.to_string(), "not a todo journal");
+    assert_eq!(fs::read(&path).unwrap(), b"hi\n");
+
+    // A header torn part way through is still reset
+    fs::write(&path, b"TODO").unwrap();
+    let mut journal = Journal::open(&path, &mut tl).unwrap();
+    assert_eq!(journal.discarded_bytes(), 4);
+    run_lines(&mut journal, &mut tl, &["add \"after reset\""]);
+    drop(journal);
+
+    let mut tl = TodoList::new();
+    Journal::open(&path, &mut tl).unwrap();
+    assert_eq!(open_indices(&tl), vec![0]);
+}
+
+#[test]
+fn test_journal_refuses_corruption_before_the_tail() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("todo.journal");
+
+    let mut tl = TodoList::new();
+    let mut journal = Journal::open(&path, &mut tl).unwrap();
+    run_lines(&mut journal, &mut tl, &["add \"first\" #a", "add \"second\" #b", "add \"third\" #c"]);
+    drop(journal);
+
+    // Flip a byte inside the first record's payload
+    let mut bytes = fs::read(&path).unwrap();
+    bytes[20] ^= 0xff;
+    fs::write(&path, &bytes).unwrap();
+
+    let mut tl = TodoList::new();
+    let err = Journal::open(&path, &mut tl).err().unwrap();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    // The intact records after the damage are left on disk
+    assert_eq!(fs::read(&path).unwrap(), bytes);
+}
+
+#[test]
+fn test_journal_refuses_oversized_records() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("todo.journal");
+
+    let mut tl = TodoList::new();
+    let mut journal = Journal::open(&path, &mut tl).unwrap();
+    let huge = format!("add \"{}\"", "x".repeat(65 << 20));
+    let out = run_lines(&mut journal, &mut tl, &["add \"small\"", &huge, "add \"after\""]);
+    assert!(out.starts_with("0\nError: journal write failed: record of "), "{}", &out[..100.min(out.len())]);
+    assert!(out.ends_with("\n1\n"));
+    // The refused add never reached the list, so the journal still rebuilds it exactly
+    assert_eq!(open_indices(&tl), vec![0, 1]);
+    drop(journal);
+
+    let mut tl = TodoList::new();
+    let journal = Journal::open(&path, &mut tl).unwrap();
+    assert_eq!(journal.discarded_bytes(), 0);
+    assert_eq!(open_indices(&tl), vec![0, 1]);
+}
+
+#[test]
+fn test_journal_is_exclusive() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("todo.journal");
+
+    let mut tl = TodoList::new();
+    let journal = Journal::open(&path, &mut tl).unwrap();
+    let err = Journal::open(&path, &mut TodoList::new()).err().unwrap();
+    assert_eq!(err.kind(), std::io::ErrorKind::WouldBlock);
+
+    drop(journal);
+    Journal::open(&path, &mut tl).unwrap();
+}
+
+#[test]
+fn test_journal_reads_next_to_a_writer() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("todo.journal");
+    assert_eq!(Journal::open_read_only(&path, &mut TodoList::new()).err().unwrap().kind(), std::io::ErrorKind::NotFound);
+
+    let mut tl = TodoList::new();
+    let mut writer = Journal::open(&path, &mut tl).unwrap();
+    run_lines(&mut writer, &mut tl, &["add \"first\" #a", "add \"second\" #b"]);
+
+    let (read, mut reader) = Journal::load_read_only(&path).unwrap();
+    assert!(reader.is_read_only());
+    assert_eq!(open_indices(&read), vec![0, 1]);
+    assert_eq!(reader.append(&parser::query("add \"x\"").unwrap()).err().unwrap().kind(), std::io::ErrorKind::PermissionDenied);
+    assert!(reader.checkpoint(&read).is_err());
+    drop(reader);
+    drop(writer);
+
+    // A torn tail is skipped but left for the next writer to deal with
+    let full_len = fs::metadata(&path).unwrap().len();
+    OpenOptions::new().write(true).open(&path).unwrap().set_len(full_len - 5).unwrap();
+    let mut read = TodoList::new();
+    let reader = Journal::open_read_only(&path, &mut read).unwrap();
+    assert!(reader.discarded_bytes() > 0);
+    assert_eq!(open_indices(&read), vec![0]);
+    assert_eq!(fs::metadata(&path).unwrap().len(), full_len - 5);
+}
+
+#[test]
+fn test_journal_load_resumes_from_checkpoint() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("todo.journal");
+
+    let (mut tl, mut journal) = Journal::load(&path).unwrap();
+    run_lines(&mut journal, &mut tl, &["add \"first\" #a", "add \"second\" #b", "done 0"]);
+    journal.checkpoint(&tl).unwrap();
+    run_lines(&mut journal, &mut tl, &["add \"third\" #c", "delete 1"]);
+    assert_eq!(journal.records_since_checkpoint(), 2);
+    drop(journal);
+
+    // Only the records after the checkpoint are replayed
+    let (tl, journal) = Journal::load(&path).unwrap();
+    assert_eq!(journal.records_since_checkpoint(), 2);
+    assert_eq!(open_indices(&tl), vec![2]);
+    assert_eq!(tl.next_index().0, 3);
+    drop(journal);
+
+    // A journal that no longer holds what the snapshot was taken from is replayed in full
+    fs::remove_file(&path).unwrap();
+    let mut tl = TodoList::new();
+    let mut journal = Journal::open(&path, &mut tl).unwrap();
+    run_lines(&mut journal, &mut tl, &["add \"other\""]);
+    drop(journal);
+    let (tl, journal) = Journal::load(&path).unwrap();
+    assert_eq!(journal.records_since_checkpoint(), 1);
+    assert_eq!(open_indices(&tl), vec![0]);
+    assert!(tl.get(todo_swamp::Index::new(2)).is_none());
+}
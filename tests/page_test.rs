@@ -0,0 +1,119 @@
+mod common;
+
+use todo_swamp::{parser, runner, storage::Journal, Index, Query, SearchPage, TodoList};
+use common::{run_lines, search_params};
+
+/// `n` items named `task <i>`, with every third one tagged `#three` and high priority
+fn build_list(n: usize) -> TodoList {
+    let lines: Vec<String> = (0..n)
+        .map(|i| format!("add \"task {i}\" {}", if i % 3 == 0 { "#three !high" } else { "" }))
+        .collect();
+    let mut tl = TodoList::new();
+    run_lines(&mut tl, &lines.iter().map(String::as_str).collect::<Vec<_>>());
+    tl
+}
+
+fn page(tl: &TodoList, line: &str) -> (Vec<u64>, usize) {
+    let SearchPage { items, total } = tl.search_page(&search_params(line));
+    (items.iter().map(|item| item.index.0).collect(), total)
+}
+
+#[test]
+fn test_limit_and_offset() {
+    let mut tl = build_list(10);
+    runner::run_line_buffered("done 1", &mut tl, &mut std::io::sink(), b"\n").unwrap();
+    assert_eq!(page(&tl, "search limit:3"), (vec![0, 2, 3], 9));
+    assert_eq!(page(&tl, "search limit:3 offset:3"), (vec![4, 5, 6], 9));
+    assert_eq!(page(&tl, "search offset:7"), (vec![8, 9], 9));
+    assert_eq!(page(&tl, "search offset:20 limit:5"), (vec![], 9));
+    assert_eq!(page(&tl, "search limit:0 #three"), (vec![], 4));
+    assert_eq!(page(&tl, "search #three limit:2 offset:1 --all"), (vec![3, 6], 4));
+
+    // Every sort order pages the same way
+    assert_eq!(page(&tl, "search sort:index:desc limit:2 offset:1"), (vec![8, 7], 9));
+    assert_eq!(page(&tl, "search sort:priority limit:3 offset:3"), (vec![9, 2, 4], 9));
+    assert_eq!(page(&tl, "search sort:description:desc limit:2 offset:1"), (vec![8, 7], 9));
+    assert_eq!(page(&tl, "search task sort:relevance offset:8"), (vec![9], 9));
+    assert_eq!(page(&tl, "search sort:due offset:1 limit:1"), (vec![2], 9));
+
+    // `--limit` caps every page
+    let tl = tl.with_limit(Some(2));
+    assert_eq!(page(&tl, "search limit:5 offset:1"), (vec![2, 3], 9));
+}
+
+#[test]
+fn test_pages_in_output() {
+    let mut tl = build_list(5);
+    let mut out = Vec::new();
+    for line in ["search limit:2 offset:1", "search #three", "search limit:9"] {
+        runner::run_line_buffered(line, &mut tl, &mut out, b"\n").unwrap();
+    }
+    assert_eq!(String::from_utf8(out).unwrap(), "\
+2 of 5 item(s) found
+1 \"task 1\"
+2 \"task 2\"
+2 item(s) found
+0 \"task 0\" #three !high
+3 \"task 3\" #three !high
+5 item(s) found
+0 \"task 0\" #three !high
+1 \"task 1\"
+2 \"task 2\"
+3 \"task 3\" #three !high
+4 \"task 4\"
+");
+    let mut out = Vec::new();
+    runner::run_line_formatted("search limit:1 offset:1 #three", &mut tl, None, &mut out, b"\n", runner::OutputFormat::JsonLines).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), "{\"type\":\"found\",\"count\":1,\"total\":2,\"items\":[{\"index\":3,\"description\":\"task 3\",\"tags\":[\"three\"],\"done\":false,\"priority\":\"high\"}]}\n");
+}
+
+fn count(tl: &TodoList, line: &str) -> usize {
+    let Query::Count(sp) = parser::query(line).unwrap() else { panic!("not a count: {}", line) };
+    tl.count(&sp)
+}
+
+#[test]
+fn test_count() {
+    // Neither `--limit` nor `limit:` truncates a count
+    let mut tl = build_list(300).with_limit(Some(5));
+    tl.done_with_index(Index::new(3));
+    tl.done_with_index(Index::new(4));
+    tl.remove(Index::new(6));
+    tl.remove(Index::new(4));
+    assert_eq!(count(&tl, "count"), 297);
+    assert_eq!(count(&tl, "count --done"), 1);
+    assert_eq!(count(&tl, "count --all"), 298);
+    assert_eq!(count(&tl, "count #three"), 98);
+    assert_eq!(count(&tl, "count #three --all limit:2 sort:due"), 99);
+    assert_eq!(count(&tl, "count 29 -#three OR (1 !high)"), 61);
+    assert_eq!(count(&tl, "count nothing"), 0);
+    // The same number a search reports as its total
+    let Query::Search(sp) = parser::query("search 29 -#three OR (1 !high)").unwrap() else { unreachable!() };
+    assert_eq!(tl.search_page(&sp).total, 61);
+
+    tl.undone_with_index(Index::new(3));
+    assert_eq!((count(&tl, "count"), count(&tl, "count --done")), (298, 0));
+}
+
+#[test]
+fn test_count_after_reload() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("todo.journal");
+    let mut tl = TodoList::new();
+    let mut journal = Journal::open(&path, &mut tl).unwrap();
+    let mut out = Vec::new();
+    for line in ["add \"a\"", "add \"b\"", "add \"c\"", "done 0", "delete 1", "count --all", "count --done"] {
+        runner::run_line_journaled(line, &mut tl, Some(&mut journal), &mut out, b"\n").unwrap();
+    }
+    drop(journal);
+    assert_eq!(String::from_utf8(out).unwrap(), "0\n1\n2\ndone\ndeleted\n2\n1\n");
+
+    let mut replayed = TodoList::new();
+    Journal::open(&path, &mut replayed).unwrap();
+    let snapshot = dir.path().join("todo.snap");
+    replayed.save_snapshot(&snapshot).unwrap();
+    let loaded = TodoList::load_snapshot(&snapshot).unwrap();
+    for tl in [&replayed, &loaded] {
+        assert_eq!((count(tl, "count"), count(tl, "count --done"), count(tl, "count --all")), (1, 1, 2));
+    }
+}
@@ -0,0 +1,213 @@
+mod common;
+
+use todo_swamp::{parser, DateOp, DateSpec, Description, Index, Priority, Query, SearchExpr, SearchParams, SearchWord, Sort, SortDirection, SortKey, StatusFilter, Tag};
+use common::search_params as search;
+
+fn word(w: &str) -> SearchExpr { SearchExpr::Word(SearchWord::new(w)) }
+fn tag(t: &str) -> SearchExpr { SearchExpr::Tag(Tag::new(t)) }
+fn not(e: SearchExpr) -> SearchExpr { SearchExpr::Not(Box::new(e)) }
+
+#[test]
+fn test_search_operator_precedence() {
+    // AND binds tighter than OR; `-` and `NOT` apply to the next term only
+    assert_eq!(search("search a b OR c -#d").expr, SearchExpr::Or(vec![
+        SearchExpr::And(vec![word("a"), word("b")]),
+        SearchExpr::And(vec![word("c"), not(tag("d"))]),
+    ]));
+    assert_eq!(search("search NOT (a OR #b) c").expr, SearchExpr::And(vec![
+        not(SearchExpr::Or(vec![word("a"), tag("b")])),
+        word("c"),
+    ]));
+}
+
+#[test]
+fn test_search_keywords_and_flags() {
+    let sp = search("search --done milk OR bread");
+    assert_eq!(sp.status, StatusFilter::Done);
+    assert_eq!(sp.expr, SearchExpr::Or(vec![word("milk"), word("bread")]));
+
+    // Lowercase `or` and words that merely start with `NOT` are ordinary words
+    assert_eq!(search("search or NOTE").expr, SearchExpr::And(vec![word("or"), word("NOTE")]));
+    assert_eq!(search("search").expr, SearchExpr::default());
+    assert!(parser::query("search (--done milk)").is_err());
+}
+
+#[test]
+fn test_single_word_search_matches_general_grammar() {
+    // A lone word takes a shortcut; it has to parse like the same word inside a group
+    for term in ["milk", "Café", "a-b", "v2", "OR", "NOT", "overdue", "-milk", "NOTE", "\u{200c}"] {
+        let fast = parser::query(&format!("search  {term}"));
+        let general = parser::query(&format!("search ({term})"));
+        assert_eq!(fast.is_ok(), general.is_ok(), "{term}");
+        if let (Ok(Query::Search(fast)), Ok(Query::Search(general))) = (fast, general) {
+            assert_eq!(fast, general, "{term}");
+        }
+    }
+    assert_eq!(search("search milk"), SearchParams { expr: word("milk"), ..SearchParams::default() });
+}
+
+#[test]
+fn test_unicode_words_and_tags() {
+    assert_eq!(parser::query("add \"Ünïcödé задача 2\" #καθαρισμός #v1"), Ok(Query::Add(
+        Description::new("Ünïcödé задача 2"),
+        vec![Tag::new("καθαρισμός"), Tag::new("v1")],
+        None,
+        None,
+    )));
+    // Persian words may contain a zero-width non-joiner
+    assert_eq!(search("search می\u{200c}خواهم").expr, word("می\u{200c}خواهم"));
+    assert!(parser::query("add \"tab\u{7}bell\"").is_err());
+    // Combining marks belong to the word they follow, as in decomposed (NFD) text
+    assert_eq!(parser::query("add \"cafe\u{301} नमस्ते\" #cafe\u{301}"), Ok(Query::Add(
+        Description::new("cafe\u{301} नमस्ते"),
+        vec![Tag::new("cafe\u{301}")],
+        None,
+        None,
+    )));
+    assert_eq!(search("search cafe\u{301}").expr, word("cafe\u{301}"));
+}
+
+#[test]
+fn test_search_words_with_punctuation() {
+    // Search text is split into words exactly like a description
+    assert_eq!(search("search v2.1").expr, SearchExpr::And(vec![word("v2"), word("1")]));
+    assert_eq!(search("search 5pm! e-mail").expr, SearchExpr::And(vec![word("5pm"), word("e-mail")]));
+    assert_eq!(search("search -re: (x,y)").expr, SearchExpr::And(vec![
+        not(word("re")),
+        SearchExpr::And(vec![word("x"), word("y")]),
+    ]));
+    assert_eq!(parser::query("search ...").unwrap_err().position, 7);
+}
+
+#[test]
+fn test_due_dates() {
+    let due = |s: &str| s.parse::<todo_swamp::Date>().unwrap();
+    assert_eq!(parser::query("add \"pay rent\" #home due:2026-11-01"), Ok(Query::Add(
+        Description::new("pay rent"),
+        vec![Tag::new("home")],
+        Some(due("2026-11-01")),
+        None,
+    )));
+    assert_eq!(parser::query("add \"x\" due:2026-02-30").unwrap_err().expected, "date (YYYY-MM-DD)");
+    assert_eq!(search("search due:<=today overdue").expr, SearchExpr::And(vec![
+        SearchExpr::Due(DateOp::OnOrBefore, DateSpec::Today),
+        SearchExpr::Overdue,
+    ]));
+    assert_eq!(search("search -due:2026-01-05").expr, not(SearchExpr::Due(DateOp::On, DateSpec::Date(due("2026-01-05")))));
+    assert_eq!(parser::query("search due:soon").unwrap_err().expected, "date (YYYY-MM-DD or today)");
+    // `due` alone and words containing `overdue` are still plain words
+    assert_eq!(search("search due overdues").expr, SearchExpr::And(vec![word("due"), word("overdues")]));
+}
+
+#[test]
+fn test_priorities() {
+    assert_eq!(parser::query("add \"x\" !high #a due:2026-11-01 #b"), Ok(Query::Add(
+        Description::new("x"),
+        vec![Tag::new("a"), Tag::new("b")],
+        Some("2026-11-01".parse().unwrap()),
+        Some(Priority::High),
+    )));
+    assert_eq!(parser::query("add \"x\" !low !high").unwrap_err().expected, "at most one due date and priority");
+    assert_eq!(parser::query("add \"x\" !urgent").unwrap_err().expected, "priority (low, medium or high)");
+    assert_eq!(parser::query("prio 3 none"), Ok(Query::Prio(Index::new(3), None)));
+    assert_eq!(parser::query("prio 3 medium"), Ok(Query::Prio(Index::new(3), Some(Priority::Medium))));
+    let sp = search("search !high milk --by-priority");
+    assert_eq!(sp.expr, SearchExpr::And(vec![SearchExpr::Priority(Priority::High), word("milk")]));
+    assert_eq!((sp.status, sp.sort), (StatusFilter::Open, Sort { key: SortKey::Priority, direction: SortDirection::Desc }));
+    assert_eq!(parser::query(r#"{"op":"prio","index":2,"priority":"low"}"#), Ok(Query::Prio(Index::new(2), Some(Priority::Low))));
+    assert_eq!(parser::query(r#"{"op":"prio","index":2}"#), Ok(Query::Prio(Index::new(2), None)));
+}
+
+#[test]
+fn test_sort_modifiers() {
+    let sort = |line: &str| search(line).sort;
+    assert_eq!(sort("search milk"), Sort { key: SortKey::Index, direction: SortDirection::Asc });
+    assert_eq!(sort("search milk sort:index:desc"), Sort { key: SortKey::Index, direction: SortDirection::Desc });
+    assert_eq!(sort("search sort:due"), Sort::by(SortKey::Due));
+    assert_eq!(sort("search sort:relevance milk"), Sort { key: SortKey::Relevance, direction: SortDirection::Desc });
+    // The last of `sort:` and `--by-priority` wins
+    assert_eq!(sort("search sort:description:asc --by-priority"), Sort::by(SortKey::Priority));
+    assert_eq!(sort("search --by-priority a OR b sort:priority:asc"), Sort { key: SortKey::Priority, direction: SortDirection::Asc });
+    assert_eq!(search("search sort:due milk").expr, word("milk"));
+    assert_eq!(parser::query("search sort:size").unwrap_err().expected, "sort key (index, relevance, priority, due, description)");
+    assert_eq!(parser::query("search sort:due:up").unwrap_err().expected, "sort direction (asc or desc)");
+    // Only at the top level; `sort` alone is still a word
+    assert!(parser::query("search (a sort:due)").is_err());
+    assert_eq!(search("search sort").expr, word("sort"));
+}
+
+#[test]
+fn test_limit_and_offset() {
+    let sp = search("search milk limit:10 offset:20");
+    assert_eq!((sp.expr, sp.limit, sp.offset), (word("milk"), Some(10), 20));
+    let sp = search("search milk");
+    assert_eq!((sp.limit, sp.offset), (None, 0));
+    assert_eq!(search("search limit:1 a OR b limit:5").limit, Some(5));
+    assert_eq!(parser::query("search limit:ten").unwrap_err().expected, "count");
+    assert_eq!(parser::query("search offset:-1").unwrap_err().expected, "count");
+    assert!(parser::query("search (a offset:1)").is_err());
+}
+
+#[test]
+fn test_count_queries() {
+    let Ok(Query::Count(sp)) = parser::query("count milk OR #dairy --all") else { panic!("not a count") };
+    assert_eq!((sp.expr, sp.status), (SearchExpr::Or(vec![word("milk"), tag("dairy")]), StatusFilter::All));
+    assert_eq!(parser::query("count"), Ok(Query::Count(SearchParams::default())));
+    assert_eq!(parser::query(r##"{"op":"count","query":"#work"}"##), Ok(Query::Count(SearchParams { expr: tag("work"), ..SearchParams::default() })));
+    let e = parser::query(r#"{"op":"count","query":"(a"}"#).unwrap_err();
+    assert_eq!((e.position, e.expected.as_str()), (2, "closing parenthesis in `query`"));
+    assert_eq!(parser::query("counts").unwrap_err().expected, "command (add, done, undone, edit, delete, prio, search, count)");
+}
+
+#[test]
+fn test_context_tags() {
+    // `#@name` is a todo.txt context; `@` is only allowed up front
+    assert_eq!(parser::query("add \"call\" #@phone #family"), Ok(Query::Add(
+        Description::new("call"),
+        vec![Tag::new("@phone"), Tag::new("family")],
+        None,
+        None,
+    )));
+    assert_eq!(search("search #@ph").expr, tag("@ph"));
+    assert!(parser::query("add \"x\" #a@b").is_err());
+    assert!(parser::query("add \"x\" #@").is_err());
+}
+
+#[test]
+fn test_edit_bare_hash_clears_tags() {
+    // Leaving the tags out keeps them; a bare `#` replaces them with none
+    assert_eq!(parser::query(r#"edit 3 "text""#), Ok(Query::Edit(Index::new(3), Some(Description::new("text")), None)));
+    assert_eq!(parser::query(r#"edit 3 "text" #"#), Ok(Query::Edit(Index::new(3), Some(Description::new("text")), Some(vec![]))));
+    assert_eq!(parser::query("edit 3 #"), Ok(Query::Edit(Index::new(3), None, Some(vec![]))));
+    assert_eq!(parser::query("edit 3 # #a").unwrap_err().expected, "tag");
+    assert_eq!(parser::query("edit 3 #a #").unwrap_err().expected, "tag name");
+}
+
+#[test]
+fn test_description_punctuation_and_escapes() {
+    assert_eq!(parser::query(r#"add "ship v2.1 by 5pm, ok?""#), Ok(Query::Add(Description::new("ship v2.1 by 5pm, ok?"), vec![], None, None)));
+    assert_eq!(parser::query(r#"add "say \"hi\" \\ bye""#), Ok(Query::Add(Description::new(r#"say "hi" \ bye"#), vec![], None, None)));
+    assert_eq!(parser::query(r#"add """#), Ok(Query::Add(Description::new(""), vec![], None, None)));
+    assert_eq!(parser::query(r#"add "a \q""#).unwrap_err().expected, r#"escape sequence (\" or \\)"#);
+    assert_eq!(parser::query(r#"add "trailing \"#).unwrap_err().expected, "closing quote");
+}
+
+#[test]
+fn test_json_commands() {
+    assert_eq!(parser::query(r##"{"op":"add","description":"say \"hi\"","tags":["#café","x"]}"##), Ok(Query::Add(
+        Description::new(r#"say "hi""#),
+        vec![Tag::new("café"), Tag::new("x")],
+        None,
+        None,
+    )));
+    assert_eq!(parser::query(r#"{"op":"edit","index":2,"tags":[]}"#), Ok(Query::Edit(Index::new(2), None, Some(vec![]))));
+    assert_eq!(parser::query(r#" {"index":4,"op":"delete"} "#), Ok(Query::Delete(Index::new(4))));
+    assert_eq!(search(r#"{"op":"search","query":"milk --done"}"#).status, StatusFilter::Done);
+
+    let err = |line: &str| parser::query(line).unwrap_err();
+    assert_eq!(err(r#"{"op":"add","description":"x","tags":["a b"]}"#).expected, "tags made of letters, digits and `-`");
+    assert_eq!(err(r#"{"op":"edit","index":1}"#).expected, "`description` or `tags` field");
+    assert!(err(r#"{"op":"done"}"#).expected.contains("missing field `index`"));
+    let e = err(r#"{"op":"search","query":"(milk"}"#);
+    assert_eq!((e.position, e.expected.as_str()), (5, "closing parenthesis in `query`"));
+}
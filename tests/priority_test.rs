@@ -0,0 +1,63 @@
+mod common;
+
+use todo_swamp::{runner, storage::Journal, Index, Priority, TodoList};
+use common::{build_list, search};
+
+#[test]
+fn test_priority_order() {
+    let mut tl = build_list(&[
+        "add \"buy milk\" !low",
+        "add \"call mom\"",
+        "add \"pay rent\" !high",
+        "add \"buy bread\" !medium",
+        "add \"fix sink\" !high",
+        "done 4",
+    ]);
+    assert_eq!(search(&tl, "search !high --all"), vec![2, 4]);
+    assert_eq!(search(&tl, "search --by-priority"), vec![2, 3, 0, 1]);
+    assert_eq!(search(&tl, "search buy --by-priority"), vec![3, 0]);
+    assert_eq!(search(&tl, "search -!low --by-priority --all"), vec![2, 4, 3, 1]);
+
+    // Raising, lowering and clearing move the item between levels
+    tl.set_priority(Index::new(1), Some(Priority::High));
+    tl.set_priority(Index::new(2), None);
+    assert_eq!(search(&tl, "search --by-priority"), vec![1, 3, 0, 2]);
+    tl.remove(Index::new(1));
+    assert!(search(&tl, "search !high").is_empty());
+}
+
+#[test]
+fn test_priority_output() {
+    let mut tl = TodoList::new();
+    let mut out = Vec::new();
+    for line in ["add \"pay rent\" !high", "prio 0 medium", "search rent", "prio 0 none", "search rent"] {
+        runner::run_line_buffered(line, &mut tl, &mut out, b"\n").unwrap();
+    }
+    assert!(runner::run_line_buffered("prio 9 low", &mut tl, &mut std::io::sink(), b"\n").is_err());
+    assert_eq!(String::from_utf8(out).unwrap(), "0\nprioritized\n1 item(s) found\n0 \"pay rent\" !medium\nprioritized\n1 item(s) found\n0 \"pay rent\"\n");
+}
+
+#[test]
+fn test_priorities_are_stored() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("todo.journal");
+    let mut tl = TodoList::new();
+    let mut journal = Journal::open(&path, &mut tl).unwrap();
+    let mut out = Vec::new();
+    for line in ["add \"pay rent\" !low", "add \"other\" !high", "prio 0 high", "prio 1 none"] {
+        runner::run_line_journaled(line, &mut tl, Some(&mut journal), &mut out, b"\n").unwrap();
+    }
+    drop(journal);
+
+    let mut replayed = TodoList::new();
+    Journal::open(&path, &mut replayed).unwrap();
+    assert_eq!(replayed.get(Index::new(0)).unwrap().priority, Some(Priority::High));
+    assert_eq!(replayed.get(Index::new(1)).unwrap().priority, None);
+    assert_eq!(search(&replayed, "search --by-priority"), vec![0, 1]);
+
+    let snapshot = dir.path().join("todo.snap");
+    replayed.save_snapshot(&snapshot).unwrap();
+    let loaded = TodoList::load_snapshot(&snapshot).unwrap();
+    assert_eq!(loaded.get(Index::new(0)).unwrap().to_string(), "0 \"pay rent\" !high");
+    assert_eq!(search(&loaded, "search !high"), vec![0]);
+}
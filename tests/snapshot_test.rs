@@ -0,0 +1,135 @@
+mod common;
+
+use std::fs;
+use std::time::Instant;
+use todo_swamp::{storage::Journal, Description, Query, SearchParams, SnapshotError, Tag, TodoList, SNAPSHOT_VERSION};
+use common::{build_list, search_lines as search};
+
+#[test]
+fn test_snapshot_round_trip() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("todo.snap");
+    let tl = build_list(&[
+        "add \"buy bread\" #groceries",
+        "add \"buy milk\" #groceries",
+        "add \"call parents\" #relatives",
+        "add \"buy presents\" #relatives",
+        "done 0",
+        "delete 3",
+    ]);
+    tl.save_snapshot(&path).unwrap();
+
+    let mut loaded = TodoList::load_snapshot(&path).unwrap();
+    for query in ["search", "search buy", "search a", "search #rel", "search b #gro"] {
+        assert_eq!(search(&tl, query), search(&loaded, query), "mismatch for `{query}`");
+    }
+
+    // The loaded list keeps numbering and indexing new items
+    assert_eq!(loaded.push(todo_swamp::Description::new("bake bread"), vec![]).0, 4);
+    assert_eq!(search(&loaded, "search bread"), vec!["4 \"bake bread\""]);
+    assert!(loaded.get(todo_swamp::Index::new(3)).is_none());
+    assert_eq!(loaded.search(&SearchParams::default()).len(), 3);
+}
+
+#[test]
+fn test_snapshot_keeps_non_ascii_and_long_terms() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("todo.snap");
+    let tl = build_list(&[
+        "add \"خرید نان\" #خانه",
+        "add \"pneumonoultramicroscopicsilicovolcanoconiosis\" #medical",
+        "add \"café au lait\" #drinks",
+        "delete 2",
+    ]);
+    tl.save_snapshot(&path).unwrap();
+
+    let loaded = TodoList::load_snapshot(&path).unwrap();
+    for query in ["search خر", "search #خا", "search pneumosilico", "search volcano", "search caf", "search lait"] {
+        assert_eq!(search(&tl, query), search(&loaded, query), "mismatch for `{query}`");
+    }
+    assert_eq!(search(&loaded, "search caf"), Vec::<String>::new());
+    assert_eq!(search(&loaded, "search pneumosilico"), vec!["1 \"pneumonoultramicroscopicsilicovolcanoconiosis\" #medical"]);
+}
+
+#[test]
+fn test_snapshot_rejects_other_version() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("todo.snap");
+    build_list(&["add \"task\" #work"]).save_snapshot(&path).unwrap();
+
+    let mut data = fs::read(&path).unwrap();
+    data[8..12].copy_from_slice(&(SNAPSHOT_VERSION + 1).to_le_bytes());
+    fs::write(&path, data).unwrap();
+
+    match TodoList::load_snapshot(&path) {
+        Err(SnapshotError::VersionMismatch { found, expected }) => {
+            assert_eq!((found, expected), (SNAPSHOT_VERSION + 1, SNAPSHOT_VERSION));
+        }
+        other => panic!("expected version mismatch, got {:?}", other.err()),
+    }
+}
+
+#[test]
+fn test_snapshot_detects_corruption() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("todo.snap");
+    build_list(&["add \"task\" #work"]).save_snapshot(&path).unwrap();
+
+    let mut data = fs::read(&path).unwrap();
+    let last = data.len() - 1;
+    data[last] ^= 0xff;
+    fs::write(&path, data).unwrap();
+
+    assert!(matches!(TodoList::load_snapshot(&path), Err(SnapshotError::ChecksumMismatch)));
+}
+
+/// A pseudo-random lowercase word drawn from `vocabulary` distinct ones
+fn word(n: u64, vocabulary: u64) -> String {
+    let mut n = n.wrapping_mul(2_654_435_761) % vocabulary;
+    let mut word = String::new();
+    loop {
+        word.push(char::from(b'a' + (n % 26) as u8));
+        n /= 26;
+        if n == 0 { break; }
+    }
+    word
+}
+
+/// Times loading a snapshot of a million items against replaying the journal that built them
+fn time_load_and_replay(vocabulary: u64) -> (f64, f64) {
+    let dir = tempfile::tempdir().unwrap();
+    let journal_path = dir.path().join("todo.journal");
+    let snapshot_path = dir.path().join("todo.snap");
+
+    let mut tl = TodoList::new();
+    let mut journal = Journal::open(&journal_path, &mut TodoList::new()).unwrap();
+    for i in 0..1_000_000 {
+        let description = Description::new(&format!("{} {} {}", word(i, vocabulary), word(i * 7 + 1, vocabulary), word(i * 13 + 5, vocabulary)));
+        let tags = vec![Tag::new(&format!("t{}", i % 1000))];
+        journal.append(&Query::Add(description.clone(), tags.clone(), None, None)).unwrap();
+        tl.push(description, tags);
+    }
+    drop(journal);
+    tl.save_snapshot(&snapshot_path).unwrap();
+
+    let start = Instant::now();
+    let loaded = TodoList::load_snapshot(&snapshot_path).unwrap();
+    let load = start.elapsed().as_secs_f64();
+    let start = Instant::now();
+    let mut replayed = TodoList::new();
+    Journal::open(&journal_path, &mut replayed).unwrap();
+    let replay = start.elapsed().as_secs_f64();
+
+    assert_eq!(loaded.search(&SearchParams::default()).len(), replayed.search(&SearchParams::default()).len());
+    (load, replay)
+}
+
+#[test]
+#[ignore] // Heavy benchmark - run with: cargo test --release -- --ignored
+fn test_snapshot_load_beats_replay() {
+    for (name, vocabulary) in [("200k words", 200_000), ("all distinct", u64::MAX)] {
+        let (load, replay) = time_load_and_replay(vocabulary);
+        println!("⏱️  1M items, {}: load {:.2}s, replay {:.2}s", name, load, replay);
+        assert!(load * 1.5 < replay, "{}: load took {:.2}s, replay {:.2}s", name, load, replay);
+    }
+}
@@ -0,0 +1,88 @@
+mod common;
+
+use todo_swamp::{parser, Query, TodoList};
+use common::{build_dated_list as build_list, search};
+
+fn sample() -> TodoList {
+    build_list(&[
+        "add \"Buy milk\" #shop due:2026-11-01 !low",
+        "add \"call mom\" #family",
+        "add \"buy bread\" #shop due:2026-10-20 !high",
+        "add \"archive mail\" due:2026-11-01",
+        "add \"buy milk and bread\" !high",
+        "add \"old task\"",
+        "done 5",
+    ])
+}
+
+#[test]
+fn test_sort_keys() {
+    let tl = sample();
+    assert_eq!(search(&tl, "search"), vec![0, 1, 2, 3, 4]);
+    assert_eq!(search(&tl, "search sort:index:desc"), vec![4, 3, 2, 1, 0]);
+    assert_eq!(search(&tl, "search buy sort:index:desc --all"), vec![4, 2, 0]);
+    // Items without the key come last whichever way it runs; ties go by index
+    assert_eq!(search(&tl, "search sort:due"), vec![2, 0, 3, 1, 4]);
+    assert_eq!(search(&tl, "search sort:due:desc"), vec![0, 3, 2, 1, 4]);
+    assert_eq!(search(&tl, "search sort:priority"), vec![2, 4, 0, 1, 3]);
+    assert_eq!(search(&tl, "search sort:priority:asc"), vec![0, 2, 4, 1, 3]);
+    // Case doesn't matter, and equal descriptions keep index order
+    assert_eq!(search(&tl, "search sort:description"), vec![3, 2, 0, 4, 1]);
+    assert_eq!(search(&tl, "search sort:description:desc #shop OR mom"), vec![1, 0, 2]);
+}
+
+#[test]
+fn test_sort_by_relevance() {
+    let tl = build_list(&[
+        "add \"birthday card\"",
+        "add \"bread\"",
+        "add \"bd\"",
+        "add \"big bad dog\"",
+        "add \"old bd-player\"",
+    ]);
+    // Exact beats prefix beats a word-start match; among those, fewer unmatched characters wins
+    assert_eq!(search(&tl, "search bd sort:relevance"), vec![2, 4, 3, 1, 0]);
+    assert_eq!(search(&tl, "search bd sort:relevance:asc"), vec![0, 1, 3, 4, 2]);
+    assert_eq!(search(&tl, "search bad sort:relevance"), vec![3, 1]);
+
+    let tl = sample();
+    // Items matching more of the terms come first, and tags outweigh words
+    assert_eq!(search(&tl, "search milk OR bread OR mom sort:relevance"), vec![4, 2, 0, 1]);
+    assert_eq!(search(&tl, "search mom OR #shop sort:relevance"), vec![0, 2, 1]);
+    // Negated terms don't count
+    assert_eq!(search(&tl, "search (buy -milk) OR bread sort:relevance"), vec![2, 4]);
+}
+
+#[test]
+fn test_relevance_scores() {
+    let tl = sample();
+    let Query::Search(sp) = parser::query("search milk OR #sh").unwrap() else { unreachable!() };
+    let scored: Vec<_> = tl.search_scored(&sp).items.into_iter().map(|(item, score)| (item.index.0, score)).collect();
+    // `milk` is exact: 4 matches, 3 consecutive, word start, prefix and exact.
+    // `#sh` is a prefix of `shop`, less 2 unmatched characters, times 1.5 for a tag.
+    let milk = 4 * 16 + 3 * 8 + 12 + 16 + 48;
+    let sh = (2 * 16 + 8 + 12 + 16 - 2) * 3 / 2;
+    assert_eq!(scored, vec![(0, milk + sh), (2, sh), (4, milk)]);
+    // Ranked by relevance, each item keeps the score it was ranked by
+    let Query::Search(sp) = parser::query("search milk OR #sh sort:relevance offset:1").unwrap() else { unreachable!() };
+    let page = tl.search_scored(&sp);
+    assert_eq!(page.total, 3);
+    assert_eq!(page.items.into_iter().map(|(item, score)| (item.index.0, score)).collect::<Vec<_>>(), vec![(4, milk), (2, sh)]);
+    assert_eq!(tl.relevance(tl.get(todo_swamp::Index::new(1)).unwrap(), &sp.expr), 0);
+
+    // Under exact matching only exact words score
+    let tl = tl.with_match_mode(todo_swamp::MatchMode::Exact);
+    let Query::Search(sp) = parser::query("search milk OR bread OR bre").unwrap() else { unreachable!() };
+    let scored: Vec<_> = tl.search_scored(&sp).items.into_iter().map(|(item, score)| (item.index.0, score)).collect();
+    assert_eq!(scored, vec![(0, milk), (2, 188), (4, milk + 188)]);
+}
+
+#[test]
+fn test_sort_with_limit() {
+    let tl = sample().with_limit(Some(2));
+    assert_eq!(search(&tl, "search sort:index:desc"), vec![4, 3]);
+    assert_eq!(search(&tl, "search sort:due:desc"), vec![0, 3]);
+    assert_eq!(search(&tl, "search sort:priority:asc"), vec![0, 2]);
+    assert_eq!(search(&tl, "search sort:description"), vec![3, 2]);
+    assert_eq!(search(&tl, "search milk OR bread sort:relevance"), vec![4, 2]);
+}
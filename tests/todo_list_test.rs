@@ -0,0 +1,123 @@
+mod common;
+
+use todo_swamp::{Description, Index, Tag, TodoList};
+use common::search;
+
+/// Spell `n` in base-26 letters after a `w`, giving a distinct word per number
+fn word(mut n: usize) -> String {
+    let mut w = String::from("w");
+    loop {
+        w.push((b'a' + (n % 26) as u8) as char);
+        n /= 26;
+        if n == 0 { return w; }
+    }
+}
+
+#[test]
+fn test_remove_compacts_term_tables() {
+    let mut tl = TodoList::new();
+    for i in 0..3000 {
+        tl.push(Description::new(&format!("{} shared", word(i))), vec![Tag::new(&word(i))]);
+    }
+    // Deleting most items empties thousands of postings, which triggers compaction
+    for i in 0..2990 {
+        assert!(tl.remove(Index::new(i)).is_some());
+    }
+    tl.compact();
+
+    assert_eq!(search(&tl, "search shared"), (2990..3000).collect::<Vec<_>>());
+    assert_eq!(search(&tl, &format!("search {}", word(2995))), vec![2995]);
+    assert_eq!(search(&tl, &format!("search #{}", word(10))), Vec::<u64>::new());
+
+    // Terms that were compacted away can be indexed again
+    let idx = tl.push(Description::new(&word(10)), vec![Tag::new(&word(10))]);
+    assert_eq!(idx.0, 3000);
+    assert_eq!(search(&tl, &format!("search {} #{}", word(10), word(10))), vec![3000]);
+    assert!(tl.remove(Index::new(0)).is_none());
+}
+
+/// Brute-force reference for the default subsequence semantics
+fn is_subsequence(sub: &str, text: &str) -> bool {
+    let mut text = text.chars();
+    sub.chars().all(|c| text.any(|t| t == c))
+}
+
+#[test]
+fn test_pair_index_matches_full_scan() {
+    let mut tl = TodoList::new();
+    let mut words = Vec::new();
+    for i in 0..2000 {
+        // Mix in repeated letters so pairs like `aa` are exercised
+        let w = format!("{}{}", word(i * 7919), word(i % 37));
+        tl.push(Description::new(&w), vec![]);
+        words.push(w);
+    }
+
+    for term in ["a", "wa", "aa", "wbw", "zyx", "wqw", "abcw", "ww", "wwz"] {
+        let expected: Vec<u64> = words.iter().enumerate()
+            .filter(|(_, w)| is_subsequence(term, w))
+            .map(|(i, _)| i as u64)
+            .collect();
+        assert_eq!(search(&tl, &format!("search {}", term)), expected, "term `{}`", term);
+    }
+}
+
+#[test]
+fn test_quad_index_matches_full_scan() {
+    // Short, wide (13 to 32 characters) and non-ASCII terms each take their own postings
+    let make = |i: usize| match i % 5 {
+        0 => format!("{}{}{}{}", word(i * 7919), word(i % 37), word(i), word(i * 13)),
+        1 => format!("{}é{}", word(i * 31), word(i % 11)),
+        _ => format!("{}{}", word(i * 7919), word(i % 37)),
+    };
+    let check = |tl: &TodoList, words: &[String]| {
+        for term in ["a", "wa", "wbw", "wqwa", "abcw", "wwwa", "wéw", "wawbwc", "wbwbwbwb"] {
+            let expected: Vec<u64> = (0..words.len() as u64)
+                .filter(|&i| tl.get(Index::new(i)).is_some() && is_subsequence(term, &words[i as usize]))
+                .collect();
+            assert_eq!(search(tl, &format!("search {}", term)), expected, "term `{}`", term);
+        }
+    };
+
+    let mut tl = TodoList::new();
+    let mut words = Vec::new();
+    // Enough new terms at once for the bulk fill, then a few at a time
+    for i in 0..6100 {
+        words.push(make(i));
+        tl.push(Description::new(&words[i]), vec![]);
+        if i == 5999 || (i > 6000 && i % 25 == 0) {
+            check(&tl, &words);
+        }
+    }
+
+    // Compaction renumbers the terms behind the postings
+    for i in 0..4000 {
+        tl.remove(Index::new(i));
+    }
+    tl.compact();
+    check(&tl, &words);
+}
+
+#[test]
+fn test_long_terms_are_searchable_without_pair_postings() {
+    let mut tl = TodoList::new();
+    // A single 4000-character word would need millions of pair postings
+    let cjk: String = (0..4000).map(|i| char::from_u32(0x4e00 + (i * 7) % 2000).unwrap()).collect();
+    tl.push(Description::new(&cjk), vec![]);
+    let long_ascii: String = (0..100).map(word).collect();
+    tl.push(Description::new(&long_ascii), vec![]);
+    tl.push(Description::new("wawb"), vec![]);
+
+    let first: String = cjk.chars().step_by(500).collect();
+    assert_eq!(search(&tl, &format!("search {}", first)), vec![0]);
+    assert_eq!(search(&tl, &format!("search {}", &long_ascii[150..160])), vec![1]);
+    assert_eq!(search(&tl, "search wawb"), vec![1, 2]);
+    assert_eq!(search(&tl, "search の"), Vec::<u64>::new());
+}
+
+#[test]
+fn test_description_words_split_on_punctuation() {
+    let d = Description::new("ship v2.1 by 5pm! -- e-mail (re: \"x\")");
+    assert_eq!(d.words().collect::<Vec<_>>(), ["ship", "v2", "1", "by", "5pm", "e-mail", "re", "x"]);
+    assert_eq!(d.escaped(), r#"ship v2.1 by 5pm! -- e-mail (re: \"x\")"#);
+}
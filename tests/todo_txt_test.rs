@@ -0,0 +1,124 @@
+mod common;
+
+use todo_swamp::{runner, storage::Journal, FixedClock, Index, Metadata, Priority, Tag, TodoItem, TodoList};
+use common::{search_lines as search, today};
+
+#[test]
+fn test_todo_txt_line_fields() {
+    let item = TodoItem::from_todo_txt(Index::new(3), "(A) 2026-01-05 call mom +family @phone about 2026-02-01 due:soon");
+    assert_eq!(item.index, Index::new(3));
+    assert_eq!(item.description.0, "call mom about 2026-02-01 due:soon");
+    assert_eq!(item.tags, vec![Tag::new("family"), Tag::new("@phone")]);
+    assert!(!item.done);
+    assert_eq!(item.priority, Some(Priority::High));
+    // `A` follows from the priority, so only the date is left over
+    assert_eq!(item.meta.as_deref(), Some(&Metadata { priority: None, created: Some("2026-01-05".into()), completed: None }));
+
+    let item = TodoItem::from_todo_txt(Index::new(0), "x 2026-01-09 2026-01-05 pay rent +home pri:B");
+    assert!(item.done);
+    assert_eq!(item.description.0, "pay rent");
+    assert_eq!(item.priority, Some(Priority::Medium));
+    assert_eq!(item.meta.as_deref(), Some(&Metadata {
+        priority: None,
+        created: Some("2026-01-05".into()),
+        completed: Some("2026-01-09".into()),
+    }));
+
+    // Things that only look like markers stay in the description
+    let item = TodoItem::from_todo_txt(Index::new(0), "xylophone (a) 2026-13-01 + +a.b @ email@host");
+    assert_eq!(item.description.0, "xylophone (a) 2026-13-01 + +a.b @ email@host");
+    assert!(item.tags.is_empty() && item.meta.is_none() && !item.done);
+
+    // Letters past `C` are low, but keep their letter
+    let item = TodoItem::from_todo_txt(Index::new(0), "(F) someday");
+    assert_eq!((item.priority, item.meta.as_deref().and_then(|m| m.priority)), (Some(Priority::Low), Some('F')));
+    let mut raised = item.clone();
+    raised.priority = Some(Priority::High);
+    assert_eq!((item.to_todo_txt(today()), raised.to_todo_txt(today())), (Ok("(F) someday".into()), Ok("(A) someday".into())));
+}
+
+#[test]
+fn test_todo_txt_round_trip() {
+    let lines = [
+        "(A) 2026-01-05 call mom +family @phone",
+        "x 2026-01-09 2026-01-05 pay rent +home pri:B",
+        "x buy milk",
+        "2026-01-01 plain",
+        "(C) @office",
+        "(Q) later",
+        "pay rent +home due:2026-11-01",
+    ];
+    for line in lines {
+        assert_eq!(TodoItem::from_todo_txt(Index::new(0), line).to_todo_txt(today()).as_deref(), Ok(line));
+    }
+
+    let tl = TodoList::import_todo_txt(format!("{}\n\n", lines.join("\n")).as_bytes()).unwrap();
+    assert_eq!(search(&tl, "search #@phone"), vec!["0 \"call mom\" #family #@phone !high"]);
+    assert_eq!(search(&tl, "search --done"), vec!["1 \"pay rent\" #home !medium", "2 \"buy milk\""]);
+    assert_eq!(search(&tl, "search due:2026-11-01"), vec!["6 \"pay rent\" #home due:2026-11-01"]);
+    assert_eq!(search(&tl, "search !low"), vec!["4 \"\" #@office !low", "5 \"later\" !low"]);
+    let mut out = Vec::new();
+    tl.export_todo_txt(&mut out).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), format!("{}\n", lines.join("\n")));
+}
+
+#[test]
+fn test_export_keeps_descriptions_that_look_like_syntax() {
+    let mut tl = TodoList::new().with_clock(FixedClock(today()));
+    for line in [
+        "add \"x ray appointment\"",
+        "add \"2026-01-01 deadline\"",
+        "add \"(A) is the grade\" !low",
+        "add \"2026-01-01 report\"",
+        "done 3",
+        "add \"x marks the spot\"",
+        "done 4",
+    ] {
+        runner::run_line_buffered(line, &mut tl, &mut std::io::sink(), b"\n").unwrap();
+    }
+    let mut out = Vec::new();
+    tl.export_todo_txt(&mut out).unwrap();
+    // A date in front keeps the leading word in the description
+    assert_eq!(String::from_utf8(out.clone()).unwrap(), "\
+2026-10-17 x ray appointment
+2026-10-17 2026-01-01 deadline
+(C) 2026-10-17 (A) is the grade
+x 2026-10-17 2026-10-17 2026-01-01 report
+x x marks the spot
+");
+    let imported = TodoList::import_todo_txt(out.as_slice()).unwrap();
+    let strip = |tl: &TodoList| tl.items().map(|item| TodoItem { meta: None, ..item.clone() }).collect::<Vec<_>>();
+    assert_eq!(strip(&imported), strip(&tl));
+
+    // Words that read back as fields can't be written at all
+    for description in ["the grade +1 for @bob", "pay due:2026-01-01", "two  spaces", " padded"] {
+        let mut tl = TodoList::new();
+        tl.push(todo_swamp::Description::new(description), vec![]);
+        let err = tl.export_todo_txt(Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert!(err.to_string().starts_with("item 0 has no todo.txt form"), "{}", err);
+    }
+    let item = TodoItem::from_todo_txt(Index::new(0), "x pay rent pri:B");
+    assert_eq!(TodoItem { description: todo_swamp::Description::new("pri:A"), ..item }.to_todo_txt(today()),
+        Err("item 0 has no todo.txt form: it would read back as `0 \"pri:B\" !high`".into()));
+}
+
+#[test]
+fn test_metadata_survives_journal_and_snapshot() {
+    let dir = tempfile::tempdir().unwrap();
+    let tl = TodoList::import_todo_txt("(A) 2026-01-05 call mom @phone\nx 2026-01-09 pay rent pri:B\n".as_bytes()).unwrap();
+    let expected: Vec<_> = tl.items().map(|item| item.to_todo_txt(today())).collect();
+
+    let journal_path = dir.path().join("todo.journal");
+    let mut journal = Journal::open(&journal_path, &mut TodoList::new()).unwrap();
+    journal.append_list(&tl).unwrap();
+    drop(journal);
+    let mut replayed = TodoList::new();
+    Journal::open(&journal_path, &mut replayed).unwrap();
+    assert_eq!(replayed.items().map(|item| item.to_todo_txt(today())).collect::<Vec<_>>(), expected);
+
+    let snapshot_path = dir.path().join("todo.snap");
+    tl.save_snapshot(&snapshot_path).unwrap();
+    let loaded = TodoList::load_snapshot(&snapshot_path).unwrap();
+    assert_eq!(loaded.items().map(|item| item.to_todo_txt(today())).collect::<Vec<_>>(), expected);
+}